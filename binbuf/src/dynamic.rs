@@ -1,5 +1,6 @@
 #![allow(type_alias_bounds)]
 
+use std::cmp::Ordering;
 pub use crate::{Entry, bytes_ptr as ptr};
 pub use ptr::Instance as Ptr;
 
@@ -95,3 +96,43 @@ impl<T: Instance> Readable<T> for &T {
         self.encode(buf)
     }
 }
+
+pub trait BufPartialEq<T: Instance>: Readable<T> + Sized {
+    fn buf_eq(self, rhs: BufConst<T>) -> bool;
+}
+
+pub trait BufEq<T: Instance>: BufPartialEq<T> {}
+
+impl<T: Decode + PartialEq> BufPartialEq<T> for &T {
+    default fn buf_eq(self, rhs: BufConst<T>) -> bool {
+        self == &T::decode(rhs).0
+    }
+}
+
+impl<T: Decode + Eq> BufEq<T> for &T {}
+
+pub trait BufPartialOrd<T: Instance>: BufPartialEq<T> {
+    fn buf_partial_cmp(self, rhs: BufConst<T>) -> Option<Ordering>;
+    fn buf_lt(self, rhs: BufConst<T>) -> bool {
+        matches!(self.buf_partial_cmp(rhs), Some(Ordering::Less))
+    }
+    fn buf_gt(self, rhs: BufConst<T>) -> bool {
+        matches!(self.buf_partial_cmp(rhs), Some(Ordering::Greater))
+    }
+}
+
+pub trait BufOrd<T: Instance>: BufPartialOrd<T> {
+    fn buf_cmp(self, rhs: BufConst<T>) -> Ordering;
+}
+
+impl<T: Decode + PartialOrd> BufPartialOrd<T> for &T {
+    default fn buf_partial_cmp(self, rhs: BufConst<T>) -> Option<Ordering> {
+        self.partial_cmp(&T::decode(rhs).0)
+    }
+}
+
+impl<T: Decode + Ord> BufOrd<T> for &T {
+    default fn buf_cmp(self, rhs: BufConst<T>) -> Ordering {
+        self.cmp(&T::decode(rhs).0)
+    }
+}
@@ -1,10 +1,12 @@
 use std::{array, marker::PhantomData};
 use crate::{bytes_ptr, fixed::{self}, BytesPtr, Entry, Fixed};
 pub use arb_num::Value as ArbNum;
+pub use ordering::{Compared, Comparator, Reverse};
 
 pub mod primitive;
 pub mod dynamic;
 pub mod arb_num;
+pub mod ordering;
 
 fixed! {
     buf! { pub struct UnitBuf<P>((), P); }
@@ -1,4 +1,5 @@
-use crate::{bytes_ptr, dynamic::{self}, BytesPtr, Dynamic};
+use std::cmp::Ordering;
+use crate::{bytes_ptr, dynamic::{self}, BytesPtr, Dynamic, Entry};
 use super::{arb_num::{self, Base}, ArbNum};
 
 dynamic! {
@@ -120,6 +121,58 @@ impl dynamic::Decode for String {
     }
 }
 
+// Bytes can't outlive the borrow of the buffer.
+fn string_buf_bytes(buf: &dynamic::BufConst<String>) -> &[u8] {
+    unsafe { dynamic::decode_ptr::<&[u8]>(String::buf_ptr(*buf)).0 }
+}
+
+// Strings are compared by their bytes, without decoding the buffer into a String.
+impl dynamic::BufPartialEq<String> for &String {
+    fn buf_eq(self, rhs: dynamic::BufConst<String>) -> bool {
+        self.as_bytes() == string_buf_bytes(&rhs)
+    }
+}
+
+impl dynamic::BufPartialOrd<String> for &String {
+    fn buf_partial_cmp(self, rhs: dynamic::BufConst<String>) -> Option<Ordering> {
+        Some(self.as_bytes().cmp(string_buf_bytes(&rhs)))
+    }
+}
+
+impl dynamic::BufOrd<String> for &String {
+    fn buf_cmp(self, rhs: dynamic::BufConst<String>) -> Ordering {
+        self.as_bytes().cmp(string_buf_bytes(&rhs))
+    }
+}
+
+impl dynamic::Readable<String> for &str {
+    fn len(&self) -> usize {
+        8 + str::len(self)
+    }
+
+    fn write_to(self, buf: dynamic::BufMut<String>) -> usize {
+        unsafe { dynamic::encode_ptr(String::buf_ptr(buf), &self.as_bytes()) }
+    }
+}
+
+impl dynamic::BufPartialEq<String> for &str {
+    fn buf_eq(self, rhs: dynamic::BufConst<String>) -> bool {
+        self.as_bytes() == string_buf_bytes(&rhs)
+    }
+}
+
+impl dynamic::BufPartialOrd<String> for &str {
+    fn buf_partial_cmp(self, rhs: dynamic::BufConst<String>) -> Option<Ordering> {
+        Some(self.as_bytes().cmp(string_buf_bytes(&rhs)))
+    }
+}
+
+impl dynamic::BufOrd<String> for &str {
+    fn buf_cmp(self, rhs: dynamic::BufConst<String>) -> Ordering {
+        self.as_bytes().cmp(string_buf_bytes(&rhs))
+    }
+}
+
 dynamic! {
     pub struct BytesPtrCLL<const LL: usize>(bytes_ptr::Const);
     buf! { pub struct BytesPtrCLLBuf<P, const LL: usize>(BytesPtrCLL<LL>, P); }
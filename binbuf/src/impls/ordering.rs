@@ -0,0 +1,129 @@
+use std::{cmp::Ordering, marker::PhantomData};
+use crate::{fixed::{self, BufConst, BufMut}, Entry, Fixed};
+
+// Defines order of values without decoding the buffer when possible.
+pub trait Comparator<T: Fixed> {
+    fn cmp(value: &T, buf: BufConst<T>) -> Ordering;
+}
+
+// Natural order of T.
+#[derive(Clone, Copy, Debug)]
+pub struct Natural;
+
+impl<T: fixed::Decode + Ord> Comparator<T> for Natural {
+    fn cmp(value: &T, buf: BufConst<T>) -> Ordering {
+        value.cmp(&T::decode(buf))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Descending<C = Natural>(PhantomData<C>);
+
+impl<T: Fixed, C: Comparator<T>> Comparator<T> for Descending<C> {
+    fn cmp(value: &T, buf: BufConst<T>) -> Ordering {
+        C::cmp(value, buf).reverse()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AsciiCaseInsensitive;
+
+impl<const N: usize> Comparator<[u8; N]> for AsciiCaseInsensitive {
+    fn cmp(value: &[u8; N], buf: BufConst<[u8; N]>) -> Ordering {
+        value.iter().map(u8::to_ascii_lowercase)
+            .cmp(<[u8; N]>::buf_ptr(buf).slice().iter().map(u8::to_ascii_lowercase))
+    }
+}
+
+// Stored exactly the same way as T, but ordered by the comparator.
+// Can be searched both by Compared and by Compared of a reference, like `Compared::new(&key)`.
+#[derive(Clone, Copy, Debug)]
+pub struct Compared<T, C>(pub T, PhantomData<C>);
+
+pub type Reverse<T> = Compared<T, Descending>;
+
+impl<T, C> Compared<T, C> {
+    pub fn new(value: T) -> Self {
+        Self(value, PhantomData)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T, C> From<T> for Compared<T, C> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+fixed! {
+    buf! { pub struct ComparedBuf<P, T: Fixed, C>(Compared<T, C>, P); }
+    impl<T: Fixed, C> I for Compared<T, C> {
+        type Buf<P> = ComparedBuf<P, T, C>;
+    }
+}
+
+fn inner_buf<T: Fixed, C, P: fixed::Ptr>(buf: fixed::Buf<Compared<T, C>, P>) -> fixed::Buf<T, P> {
+    unsafe { T::buf(Compared::<T, C>::buf_ptr(buf)) }
+}
+
+impl<T: Fixed, C> Fixed for Compared<T, C> {
+    const LEN: usize = T::LEN;
+    fn encode(&self, buf: BufMut<Self>) {
+        self.0.encode(inner_buf(buf));
+    }
+}
+
+impl<T: fixed::Decode, C> fixed::Decode for Compared<T, C> {
+    fn decode(buf: BufConst<Self>) -> Self {
+        Self::new(T::decode(inner_buf(buf)))
+    }
+}
+
+impl<T: Fixed, C: Comparator<T>> fixed::BufPartialEq<Compared<T, C>> for &Compared<T, C> {
+    fn buf_eq(self, rhs: BufConst<Compared<T, C>>) -> bool {
+        C::cmp(&self.0, inner_buf(rhs)) == Ordering::Equal
+    }
+}
+
+impl<T: Fixed, C: Comparator<T>> fixed::BufEq<Compared<T, C>> for &Compared<T, C> {}
+
+impl<T: Fixed, C: Comparator<T>> fixed::BufPartialOrd<Compared<T, C>> for &Compared<T, C> {
+    fn buf_partial_cmp(self, rhs: BufConst<Compared<T, C>>) -> Option<Ordering> {
+        Some(C::cmp(&self.0, inner_buf(rhs)))
+    }
+}
+
+impl<T: Fixed, C: Comparator<T>> fixed::BufOrd<Compared<T, C>> for &Compared<T, C> {
+    fn buf_cmp(self, rhs: BufConst<Compared<T, C>>) -> Ordering {
+        C::cmp(&self.0, inner_buf(rhs))
+    }
+}
+
+impl<'a, T: Fixed, C> fixed::Readable<Compared<T, C>> for Compared<&'a T, C> {
+    fn write_to(self, buf: BufMut<Compared<T, C>>) {
+        self.0.encode(inner_buf(buf));
+    }
+}
+
+impl<'a, T: Fixed, C: Comparator<T>> fixed::BufPartialEq<Compared<T, C>> for Compared<&'a T, C> {
+    fn buf_eq(self, rhs: BufConst<Compared<T, C>>) -> bool {
+        C::cmp(self.0, inner_buf(rhs)) == Ordering::Equal
+    }
+}
+
+impl<'a, T: Fixed, C: Comparator<T>> fixed::BufEq<Compared<T, C>> for Compared<&'a T, C> {}
+
+impl<'a, T: Fixed, C: Comparator<T>> fixed::BufPartialOrd<Compared<T, C>> for Compared<&'a T, C> {
+    fn buf_partial_cmp(self, rhs: BufConst<Compared<T, C>>) -> Option<Ordering> {
+        Some(C::cmp(self.0, inner_buf(rhs)))
+    }
+}
+
+impl<'a, T: Fixed, C: Comparator<T>> fixed::BufOrd<Compared<T, C>> for Compared<&'a T, C> {
+    fn buf_cmp(self, rhs: BufConst<Compared<T, C>>) -> Ordering {
+        C::cmp(self.0, inner_buf(rhs))
+    }
+}
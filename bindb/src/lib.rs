@@ -3,7 +3,8 @@
 pub mod storage;
 mod private;
 mod utils;
-//mod tests;
+#[cfg(test)]
+mod tests;
 
 
 // fn benchmark<R>(msg: &'static str, f: impl FnOnce() -> R) -> R {
@@ -2,13 +2,39 @@ pub use fixed::Value as Fixed;
 pub use dynamic::Value as Dynamic;
 pub use indexed_dynamic::Value as IndexedDynamic;
 pub use single::Value as Single;
+pub use bitmap::Value as Bitmap;
+pub use bloom::Value as Bloom;
 pub use binary_tree::Value as BinaryTree;
+pub use b_tree::Value as BTree;
+pub use dynamic_binary_tree::Value as DynamicBinaryTree;
+pub use binary_tree_multi::Value as BinaryTreeMulti;
+pub use interner::Value as Interner;
+pub use hash_index::Value as HashIndex;
+pub use radix_trie::Value as RadixTrie;
+pub use queue::Value as Queue;
+pub use log::Value as Log;
+pub use heap::Value as Heap;
+pub use multi_list::Value as MultiList;
+pub use blob::Value as Blob;
 
 pub mod fixed;
 pub mod dynamic;
 pub mod indexed_dynamic;
 pub mod binary_tree;
+pub mod b_tree;
+pub mod dynamic_binary_tree;
+pub mod binary_tree_multi;
+pub mod interner;
+pub mod hash_index;
+pub mod radix_trie;
+pub mod queue;
+pub mod log;
+pub mod heap;
+pub mod multi_list;
+pub mod blob;
 pub mod single;
+pub mod bitmap;
+pub mod bloom;
 
 #[derive(Clone, Copy, Debug)]
 pub enum OpenMode {
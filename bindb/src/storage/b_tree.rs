@@ -0,0 +1,418 @@
+use std::{cmp::Ordering, fs::File, marker::PhantomData};
+use binbuf::{bytes_ptr, fixed::Readable, BytesPtr, Entry, Fixed as _};
+use super::OpenMode;
+
+pub const PAGE_LEN: usize = 4096;
+
+type Page = [u8; PAGE_LEN];
+
+const LEAF: u8 = 0;
+const INTERNAL: u8 = 1;
+
+binbuf::fixed! {
+    // Stored at the start of every page.
+    // Leaf pages continue with key-value pairs.
+    // Internal pages continue with id of the first child, followed by pairs of key and id of the child to the right of it.
+    struct PageHeader {
+        #[lens(buf_kind)]
+        kind: u8,
+        #[lens(buf_len)]
+        len: u16, // count of keys in the page
+        #[lens(buf_next_id)]
+        next_id: Option<u64>, // next leaf page, only used by leaf pages
+    }
+    buf! { struct PageHeaderBuf<P>(PageHeader, P); }
+
+    impl I for PageHeader {
+        type Buf<P> = PageHeaderBuf<P>;
+    }
+    impl Code for PageHeader {}
+}
+
+binbuf::fixed! {
+    pub struct Header {
+        #[lens(buf_root_id)]
+        root_id: u64,
+        #[lens(buf_len)]
+        len: u64,
+    }
+    buf! { pub struct HeaderBuf<P>(Header, P); }
+
+    impl I for Header {
+        type Buf<P> = HeaderBuf<P>;
+    }
+    impl Code for Header {}
+}
+
+#[derive(Debug)]
+pub enum AddError {
+    AddPage(super::fixed::AddError),
+}
+
+#[derive(Debug)]
+pub enum OpenError {
+    FixedOpen(super::fixed::OpenError),
+    SingleOpen(super::single::OpenError),
+    AddRootPage(super::fixed::AddError),
+    // Keys and values are too big to fit at least 3 of them into a page.
+    EntryTooBig,
+}
+
+pub struct OpenFiles {
+    pub pages: File,
+    pub header: File,
+}
+
+pub struct OpenMaxMargins {
+    pub pages: u64,
+}
+
+pub struct OpenConfig {
+    pub mode: OpenMode,
+    pub files: OpenFiles,
+    pub max_margins: OpenMaxMargins,
+}
+
+#[derive(Clone, Copy)]
+struct PageParent {
+    id: u64,
+    child_idx: usize,
+}
+
+fn page_header<P: BytesPtr>(page: P) -> binbuf::Buf<PageHeader, P> {
+    unsafe { PageHeader::buf(page.range_at(0, PageHeader::LEN)) }
+}
+
+fn page_kind<P: BytesPtr>(page: P) -> u8 {
+    binbuf::fixed::decode::<u8, _>(PageHeader::buf_kind(page_header(page)))
+}
+
+fn page_len<P: BytesPtr>(page: P) -> usize {
+    binbuf::fixed::decode::<u16, _>(PageHeader::buf_len(page_header(page))) as usize
+}
+
+fn set_page_len(page: bytes_ptr::Mut, len: usize) {
+    (len as u16).encode(PageHeader::buf_len(page_header(page)));
+}
+
+fn page_next_id<P: BytesPtr>(page: P) -> Option<u64> {
+    binbuf::fixed::decode::<Option<u64>, _>(PageHeader::buf_next_id(page_header(page)))
+}
+
+fn set_page_next_id(page: bytes_ptr::Mut, id: Option<u64>) {
+    id.encode(PageHeader::buf_next_id(page_header(page)));
+}
+
+pub struct Iter<'a, K, V> {
+    tree: &'a Value<K, V>,
+    page_id: Option<u64>,
+    idx: usize,
+}
+
+impl<'a, K: binbuf::Fixed, V: binbuf::Fixed> Iterator for Iter<'a, K, V> {
+    type Item = (binbuf::BufConst<K>, binbuf::BufConst<V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let page = self.tree.page(self.page_id?);
+            if self.idx < page_len(page) {
+                let idx = self.idx;
+                self.idx += 1;
+                return Some((Value::<K, V>::leaf_key(page, idx), Value::<K, V>::leaf_value(page, idx)));
+            }
+            self.page_id = page_next_id(page);
+            self.idx = 0;
+        }
+    }
+}
+
+// B+ tree map, many sorted keys are stored per page and values are only stored in leaf pages.
+// Pages aren't merged when they become underfull after removals.
+pub struct Value<K, V> {
+    pages: super::Fixed<Page>,
+    header: super::Single<Header>,
+    root_id: u64,
+    len: u64,
+    _marker: PhantomData<fn() -> (K, V)>
+}
+
+impl<K: binbuf::Fixed, V: binbuf::Fixed> Value<K, V> {
+    const LEAF_ENTRY_LEN: usize = K::LEN + V::LEN;
+    const INTERNAL_ENTRY_LEN: usize = K::LEN + 8;
+    const LEAF_CAPACITY: usize = (PAGE_LEN - PageHeader::LEN) / Self::LEAF_ENTRY_LEN;
+    const INTERNAL_CAPACITY: usize = (PAGE_LEN - PageHeader::LEN - 8) / Self::INTERNAL_ENTRY_LEN;
+
+    pub unsafe fn open(OpenConfig { mode, files, max_margins }: OpenConfig) -> Result<Self, OpenError> {
+        if Self::LEAF_CAPACITY < 3 || Self::INTERNAL_CAPACITY < 3 {
+            return Err(OpenError::EntryTooBig);
+        }
+        let pages = super::Fixed::open(mode, files.pages, max_margins.pages)
+            .map_err(OpenError::FixedOpen)?;
+
+        let header = super::Single::open(
+            match mode {
+                OpenMode::New => super::single::OpenMode::New(&Header { root_id: 0, len: 0 }),
+                OpenMode::Existing => super::single::OpenMode::Existing,
+            },
+            files.header,
+        )
+            .map_err(OpenError::SingleOpen)?;
+
+        let Header { root_id, len } = header.get();
+        let mut value = Self { pages, header, root_id, len, _marker: PhantomData };
+        if let OpenMode::New = mode {
+            value.add_page(LEAF).map_err(OpenError::AddRootPage)?;
+        }
+        Ok(value)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn set_len(&mut self, len: u64) {
+        len.encode(Header::buf_len(self.header.buf_mut()));
+        self.len = len;
+    }
+
+    fn set_root_id(&mut self, id: u64) {
+        id.encode(Header::buf_root_id(self.header.buf_mut()));
+        self.root_id = id;
+    }
+
+    fn page(&self, id: u64) -> bytes_ptr::Const {
+        <Page as Entry>::buf_ptr(unsafe { self.pages.buf_unchecked(id) })
+    }
+
+    fn page_mut(&mut self, id: u64) -> bytes_ptr::Mut {
+        <Page as Entry>::buf_ptr(unsafe { self.pages.buf_mut_unchecked(id) })
+    }
+
+    fn add_page(&mut self, kind: u8) -> Result<u64, super::fixed::AddError> {
+        let id = self.pages.add(&[0u8; PAGE_LEN])?;
+        PageHeader { kind, len: 0, next_id: None }.encode(page_header(self.page_mut(id)));
+        Ok(id)
+    }
+
+    // Replaces entries of the page, which start right after the page header.
+    fn write_page_entries(&mut self, id: u64, entries: &[u8], len: usize) {
+        let page = self.page_mut(id);
+        unsafe { page.range_at(PageHeader::LEN, entries.len()) }.copy_from_slice(entries);
+        set_page_len(page, len);
+    }
+
+    fn leaf_entry_offset(idx: usize) -> usize {
+        PageHeader::LEN + idx * Self::LEAF_ENTRY_LEN
+    }
+
+    fn internal_child_offset(idx: usize) -> usize {
+        PageHeader::LEN + idx * Self::INTERNAL_ENTRY_LEN
+    }
+
+    fn internal_key_offset(idx: usize) -> usize {
+        Self::internal_child_offset(idx) + 8
+    }
+
+    fn leaf_key<P: BytesPtr>(page: P, idx: usize) -> binbuf::Buf<K, P> {
+        unsafe { K::buf(page.range_at(Self::leaf_entry_offset(idx), K::LEN)) }
+    }
+
+    fn leaf_value<P: BytesPtr>(page: P, idx: usize) -> binbuf::Buf<V, P> {
+        unsafe { V::buf(page.range_at(Self::leaf_entry_offset(idx) + K::LEN, V::LEN)) }
+    }
+
+    fn internal_child_id(page: bytes_ptr::Const, idx: usize) -> u64 {
+        unsafe { binbuf::fixed::decode_ptr::<u64>(page.range_at(Self::internal_child_offset(idx), 8)) }
+    }
+
+    // Binary search over first len keys of the page.
+    fn search_keys(
+        page: bytes_ptr::Const,
+        len: usize,
+        key: impl binbuf::fixed::BufOrd<K> + Clone,
+        key_offset: impl Fn(usize) -> usize
+    ) -> Result<usize, usize> {
+        let (mut low, mut high) = (0, len);
+        while low < high {
+            let mid = (low + high) / 2;
+            let mid_key = unsafe { K::buf(page.range_at(key_offset(mid), K::LEN)) };
+            match key.clone().buf_cmp(mid_key) {
+                Ordering::Less => high = mid,
+                Ordering::Equal => return Ok(mid),
+                Ordering::Greater => low = mid + 1,
+            }
+        }
+        Err(low)
+    }
+
+    // Returns internal pages passed on the way down, id of the leaf page and position of the key in it.
+    fn search(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> (Vec<PageParent>, u64, Result<usize, usize>) {
+        let mut path = Vec::new();
+        let mut page_id = self.root_id;
+        loop {
+            let page = self.page(page_id);
+            let len = page_len(page);
+            if page_kind(page) == LEAF {
+                return (path, page_id, Self::search_keys(page, len, key, Self::leaf_entry_offset));
+            }
+            // Keys equal to the separator are stored to the right of it.
+            let child_idx = match Self::search_keys(page, len, key.clone(), Self::internal_key_offset) {
+                Ok(idx) => idx + 1,
+                Err(idx) => idx,
+            };
+            path.push(PageParent { id: page_id, child_idx });
+            page_id = Self::internal_child_id(page, child_idx);
+        }
+    }
+
+    pub fn contains_key(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> bool {
+        self.search(key).2.is_ok()
+    }
+
+    pub fn buf(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Option<binbuf::BufConst<V>> {
+        let (_, leaf_id, idx) = self.search(key);
+        idx.ok().map(|idx| Self::leaf_value(self.page(leaf_id), idx))
+    }
+
+    pub fn buf_mut(&mut self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Option<binbuf::BufMut<V>> {
+        let (_, leaf_id, idx) = self.search(key);
+        let idx = idx.ok()?;
+        Some(Self::leaf_value(self.page_mut(leaf_id), idx))
+    }
+
+    // Returns true if item already exists.
+    pub fn add(&mut self, key: impl binbuf::fixed::BufOrd<K> + Clone, value: impl Readable<V>) -> Result<bool, AddError> {
+        let (path, leaf_id, idx) = self.search(key.clone());
+        let idx = match idx {
+            Ok(_) => return Ok(true),
+            Err(idx) => idx,
+        };
+        let len = page_len(self.page(leaf_id));
+
+        if len < Self::LEAF_CAPACITY {
+            let page = self.page_mut(leaf_id);
+            page.copy_within(
+                Self::leaf_entry_offset(idx) .. Self::leaf_entry_offset(len),
+                Self::leaf_entry_offset(idx + 1)
+            );
+            key.write_to(Self::leaf_key(page, idx));
+            value.write_to(Self::leaf_value(page, idx));
+            set_page_len(page, len + 1);
+        } else {
+            let right_id = self.add_page(LEAF).map_err(AddError::AddPage)?;
+
+            // Entries together with the new one are split in halves between the old and the new page.
+            let mut entries = self.page(leaf_id).slice()[Self::leaf_entry_offset(0) .. Self::leaf_entry_offset(len)].to_vec();
+            let offset = idx * Self::LEAF_ENTRY_LEN;
+            entries.splice(offset .. offset, std::iter::repeat(0).take(Self::LEAF_ENTRY_LEN));
+            let entry = unsafe { bytes_ptr::Mut::from_slice(&mut entries[offset .. offset + Self::LEAF_ENTRY_LEN]) };
+            key.write_to(unsafe { K::buf(entry.range_at(0, K::LEN)) });
+            value.write_to(unsafe { V::buf(entry.range_at(K::LEN, V::LEN)) });
+
+            let left_len = (len + 1) / 2;
+            let split_offset = left_len * Self::LEAF_ENTRY_LEN;
+            let next_id = page_next_id(self.page(leaf_id));
+            self.write_page_entries(leaf_id, &entries[.. split_offset], left_len);
+            set_page_next_id(self.page_mut(leaf_id), Some(right_id));
+            self.write_page_entries(right_id, &entries[split_offset ..], len + 1 - left_len);
+            set_page_next_id(self.page_mut(right_id), next_id);
+
+            let separator = entries[split_offset .. split_offset + K::LEN].to_vec();
+            self.add_to_parent(path, leaf_id, separator, right_id)?;
+        }
+        self.set_len(self.len + 1);
+        Ok(false)
+    }
+
+    // Adds separator key and the page to the right of it after a split.
+    // Parent pages are split as well while they are full, up to the root.
+    fn add_to_parent(
+        &mut self,
+        mut path: Vec<PageParent>,
+        mut left_id: u64,
+        mut separator: Vec<u8>,
+        mut right_id: u64
+    ) -> Result<(), AddError> {
+        loop {
+            let Some(parent) = path.pop() else {
+                let root_id = self.add_page(INTERNAL).map_err(AddError::AddPage)?;
+                let mut entries = vec![0u8; 8 + Self::INTERNAL_ENTRY_LEN];
+                unsafe {
+                    binbuf::fixed::encode_slice::<u64>(&mut entries[0 .. 8], &left_id);
+                    binbuf::fixed::encode_slice::<u64>(&mut entries[8 + K::LEN ..], &right_id);
+                }
+                entries[8 .. 8 + K::LEN].copy_from_slice(&separator);
+                self.write_page_entries(root_id, &entries, 1);
+                self.set_root_id(root_id);
+                return Ok(());
+            };
+
+            let len = page_len(self.page(parent.id));
+            // Separator goes right after the child that was split, followed by id of the new page.
+            let offset = Self::internal_key_offset(parent.child_idx);
+            if len < Self::INTERNAL_CAPACITY {
+                let page = self.page_mut(parent.id);
+                page.copy_within(offset .. Self::internal_key_offset(len), offset + Self::INTERNAL_ENTRY_LEN);
+                unsafe {
+                    page.range_at(offset, K::LEN).copy_from_slice(&separator);
+                    binbuf::fixed::encode_ptr::<u64>(page.range_at(offset + K::LEN, 8), &right_id);
+                }
+                set_page_len(page, len + 1);
+                return Ok(());
+            }
+
+            let new_id = self.add_page(INTERNAL).map_err(AddError::AddPage)?;
+            let mut entries = self.page(parent.id).slice()[Self::internal_child_offset(0) .. Self::internal_key_offset(len)].to_vec();
+            let offset = offset - Self::internal_child_offset(0);
+            let mut right_id_bytes = [0u8; 8];
+            unsafe { binbuf::fixed::encode_slice::<u64>(&mut right_id_bytes, &right_id) };
+            entries.splice(offset .. offset, separator.iter().copied().chain(right_id_bytes));
+
+            // Middle key moves up to the parent, keys after it move to the new page.
+            let mid = (len + 1) / 2;
+            let mid_offset = 8 + mid * Self::INTERNAL_ENTRY_LEN;
+            self.write_page_entries(parent.id, &entries[.. mid_offset], mid);
+            self.write_page_entries(new_id, &entries[mid_offset + K::LEN ..], len - mid);
+
+            separator = entries[mid_offset .. mid_offset + K::LEN].to_vec();
+            left_id = parent.id;
+            right_id = new_id;
+        }
+    }
+
+    // Returns true if item doesn't exist.
+    pub fn remove(&mut self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> bool {
+        let (_, leaf_id, idx) = self.search(key);
+        let Ok(idx) = idx else {
+            return true;
+        };
+        let page = self.page_mut(leaf_id);
+        let len = page_len(page);
+        page.copy_within(
+            Self::leaf_entry_offset(idx + 1) .. Self::leaf_entry_offset(len),
+            Self::leaf_entry_offset(idx)
+        );
+        set_page_len(page, len - 1);
+        self.set_len(self.len - 1);
+        false
+    }
+
+    // Iterates items in order of keys, following links between leaf pages.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut page_id = self.root_id;
+        while page_kind(self.page(page_id)) == INTERNAL {
+            page_id = Self::internal_child_id(self.page(page_id), 0);
+        }
+        Iter { tree: self, page_id: Some(page_id), idx: 0 }
+    }
+}
+
+impl<K: binbuf::Fixed, V: binbuf::fixed::Decode> Value<K, V> {
+    pub fn get(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Option<V> {
+        self.buf(key).map(|buf| binbuf::fixed::decode::<V, _>(buf))
+    }
+}
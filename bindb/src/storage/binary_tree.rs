@@ -1,8 +1,8 @@
-use std::{fmt::Debug, fs::File};
+use std::{cmp::Ordering, fmt::Debug, fs::File, ops::{Bound, RangeBounds}};
 use binbuf::{bytes_ptr, fixed::Readable, impls::{arb_num, ArbNum}, BytesPtr, Entry, Fixed as _};
 use super::OpenMode;
 
-mod search;
+pub mod search;
 
 pub trait NodeId: binbuf::fixed::Decode {
     fn to_u64(self) -> u64;
@@ -36,8 +36,47 @@ impl NodeId for u16 {
     }
 }
 
+// Count of nodes in the subtree, it's stored in nodes to find items by their position.
+// With () counts aren't tracked and take no space.
+pub trait NodeSize: binbuf::fixed::Decode {
+    fn to_u64(self) -> u64;
+    fn from_u64(value: u64) -> Self;
+}
+
+impl NodeSize for () {
+    fn from_u64(_value: u64) -> Self {}
+    fn to_u64(self) -> u64 {
+        0
+    }
+}
+
+impl<const LEN: usize> NodeSize for ArbNum<LEN, u64> {
+    fn from_u64(value: u64) -> Self {
+        ArbNum::new(value)
+    }
+    fn to_u64(self) -> u64 {
+        self.unwrap()
+    }
+}
+
+impl NodeSize for u64 {
+    fn from_u64(value: u64) -> Self {
+        value
+    }
+    fn to_u64(self) -> u64 {
+        self
+    }
+}
+
+// Sizes which are actually tracked, unlike (), so positions of items can be found.
+pub trait TracksSize: NodeSize {}
+
+impl<const LEN: usize> TracksSize for ArbNum<LEN, u64> {}
+
+impl TracksSize for u64 {}
+
 binbuf::fixed! {
-    pub struct Node<I: NodeId, K, V> {
+    pub struct Node<I: NodeId, K, V, S: NodeSize> {
         #[lens(buf_key)]
         key: K,
         #[lens(buf_value)]
@@ -46,21 +85,27 @@ binbuf::fixed! {
         left_id: I,
         #[lens(buf_right_id)]
         right_id: I,
+        #[lens(buf_height)]
+        height: u8, // height of the subtree, 1 for leaf nodes
+        #[lens(buf_size)]
+        size: S,
     }
-    buf! { pub struct NodeBuf<P, I: NodeId, K: binbuf::Fixed, V: binbuf::Fixed>(Node<I, K, V>, P); }
+    buf! { pub struct NodeBuf<P, I: NodeId, K: binbuf::Fixed, V: binbuf::Fixed, S: NodeSize>(Node<I, K, V, S>, P); }
 
-    impl<I: NodeId, K: binbuf::Fixed, V: binbuf::Fixed> I for Node<I, K, V> {
-        type Buf<P> = NodeBuf<P, I, K, V>;
+    impl<I: NodeId, K: binbuf::Fixed, V: binbuf::Fixed, S: NodeSize> I for Node<I, K, V, S> {
+        type Buf<P> = NodeBuf<P, I, K, V, S>;
     }
 
-    impl<I: NodeId, K: binbuf::Fixed, V: binbuf::Fixed> Encode for Node<I, K, V> {}
-    impl<I: NodeId, K: binbuf::fixed::Decode, V: binbuf::fixed::Decode> Decode for Node<I, K, V> {}
+    impl<I: NodeId, K: binbuf::Fixed, V: binbuf::Fixed, S: NodeSize> Encode for Node<I, K, V, S> {}
+    impl<I: NodeId, K: binbuf::fixed::Decode, V: binbuf::fixed::Decode, S: NodeSize> Decode for Node<I, K, V, S> {}
 }
 
 binbuf::fixed! {
     pub struct Header {
         #[lens(buf_root_id)]
         root_id: Option<u64>,
+        #[lens(buf_len)]
+        len: u64,
     }
     buf! { pub struct HeaderBuf<P>(Header, P); }
 
@@ -70,16 +115,35 @@ binbuf::fixed! {
     impl Code for Header {}
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    pub len: u64,
+    pub depth: u8, // count of nodes on the longest path from the root
+    pub average_depth: f64,
+    pub free_ids: u64,
+    pub utilisation: f64, // share of the nodes file taken by live nodes
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum NodeBranch {
     Left,
     Right
 }
 
+impl NodeBranch {
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum AddError {
     AddNode(super::fixed::AddError),
-    RemoveLastFreeId(super::fixed::RemoveLastError)
+    RemoveLastFreeId(super::fixed::RemoveLastError),
+    AddToBloom(super::bloom::AddError),
 }
 
 #[derive(Debug)]
@@ -106,8 +170,27 @@ pub enum OpenError {
     SingleOpen(super::single::OpenError),
 }
 
+#[derive(Debug)]
+pub enum BulkLoadError {
+    NodesNotEmpty,
+    FreeIdsNotEmpty,
+    ReserveNodes(super::fixed::ReserveError),
+    AddNode(super::fixed::AddError),
+    AddToBloom(super::bloom::AddError),
+    // Iterator returned a different count of items than its length.
+    WrongLen,
+    RemoveNode(super::fixed::RemoveLastError),
+}
+
+#[derive(Debug)]
+pub enum FromSortedIterError {
+    Open(OpenError),
+    BulkLoad(BulkLoadError),
+}
+
+// Path holds every node passed on the way from the root, it's needed to rebalance the tree after changes.
 pub struct Searched {
-    parent: Option<NodeParent>,
+    path: Vec<NodeParent>,
     id: Option<u64>
 }
 
@@ -118,27 +201,64 @@ impl Searched {
 
     pub fn find(self) -> Result<SearchedFound, SearchedNotFound> {
         match self.id {
-            Some(id) => Ok(SearchedFound { parent: self.parent, id }),
-            None => Err(SearchedNotFound { parent: self.parent })
+            Some(id) => Ok(SearchedFound { path: self.path, id }),
+            None => Err(SearchedNotFound { path: self.path })
         }
     }
 }
 
 pub struct SearchedFound {
-    parent: Option<NodeParent>,
+    path: Vec<NodeParent>,
     id: u64,
 }
 
 pub struct SearchedNotFound {
-    parent: Option<NodeParent>,
+    path: Vec<NodeParent>,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct NodeParent {
     id: u64,
     branch: NodeBranch,
 }
 
+// In-order iterator over a range of nodes.
+// Both stacks hold nodes whose subtrees towards the other end aren't visited yet, next node is on top.
+pub struct Iter<'a, I: NodeId, K, V, S: NodeSize> {
+    tree: &'a Value<I, K, V, S>,
+    front: Vec<u64>,
+    back: Vec<u64>,
+}
+
+impl<'a, I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed, S: NodeSize> Iterator for Iter<'a, I, K, V, S> {
+    type Item = (binbuf::BufConst<K>, binbuf::BufConst<V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.front.pop()?;
+        // Iteration ends once both ends reach the same node.
+        if self.back.last() == Some(&id) {
+            self.front.clear();
+            self.back.clear();
+        } else {
+            self.tree.push_branch(&mut self.front, self.tree.child_id(id, NodeBranch::Right), NodeBranch::Left);
+        }
+        Some(self.tree.item(id))
+    }
+}
+
+impl<'a, I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed, S: NodeSize> DoubleEndedIterator for Iter<'a, I, K, V, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let id = self.back.pop()?;
+        if self.front.last() == Some(&id) {
+            self.front.clear();
+            self.back.clear();
+        } else {
+            self.tree.push_branch(&mut self.back, self.tree.child_id(id, NodeBranch::Left), NodeBranch::Right);
+        }
+        Some(self.tree.item(id))
+    }
+}
+
 pub struct OpenFiles {
     pub nodes: File,
     pub free_ids: File,
@@ -156,82 +276,365 @@ pub struct OpenConfig {
     pub max_margins: OpenMaxMargins
 }
 
-pub struct Value<I: NodeId, K, V> {
-    nodes: super::Fixed<Node<I, K, V>>,
+pub struct Value<I: NodeId, K, V, S: NodeSize = ()> {
+    nodes: super::Fixed<Node<I, K, V, S>>,
     free_ids: super::Fixed<u64>,
     header: super::Single<Header>,
     root_id: Option<u64>,
+    len: u64,
+    bloom: Option<super::Bloom<K>>, // lets lookups of missing keys skip the search
 }
 
-impl<I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed> Value<I, K, V> {
+impl<I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed, S: NodeSize> Value<I, K, V, S> {
     pub unsafe fn open(OpenConfig { mode, files, max_margins }: OpenConfig) -> Result<Self, OpenError> {
         let nodes = super::Fixed::open(mode, files.nodes, max_margins.nodes)
             .map_err(OpenError::FixedOpen)?;
 
         let header = super::Single::open(
             match mode {
-                OpenMode::New => super::single::OpenMode::New(&Header { root_id: None }),
+                OpenMode::New => super::single::OpenMode::New(&Header { root_id: None, len: 0 }),
                 OpenMode::Existing => super::single::OpenMode::Existing,
             },
             files.header,
         )
             .map_err(OpenError::SingleOpen)?;
 
-        let root_id = header.get().root_id;
+        let Header { root_id, len } = header.get();
 
         Ok(Self {
             nodes,
             free_ids: super::Fixed::open(mode, files.free_ids, max_margins.free_ids).map_err(OpenError::FixedOpen)?,
             header,
             root_id,
+            len,
+            bloom: None,
         })
     }
 
-    unsafe fn node_buf_by_id(&self, id: u64) -> binbuf::BufConst<Node<I, K, V>> {
+    // Keys of the tree are added to the filter, and every added key is added to it from now on.
+    // Filter hashes encoded keys, so it's only available for keys which are equal by their bytes.
+    pub fn attach_bloom(&mut self, mut bloom: super::Bloom<K>) -> Result<(), super::bloom::AddError>
+    where K: super::bloom::BytesEq {
+        for (key_buf, _) in self.iter() {
+            bloom.add_buf(key_buf)?;
+        }
+        self.bloom = Some(bloom);
+        Ok(())
+    }
+
+    pub fn detach_bloom(&mut self) -> Option<super::Bloom<K>> {
+        self.bloom.take()
+    }
+
+    // Returns false if the key surely doesn't exist.
+    fn may_contain(&self, key: impl Readable<K>) -> bool {
+        self.bloom.as_ref().map_or(true, |bloom| bloom.may_contain(key))
+    }
+
+    // Opens the tree and bulk loads it with the items, an existing tree must be empty.
+    pub unsafe fn from_sorted_iter<KR: binbuf::fixed::Readable<K>, VR: binbuf::fixed::Readable<V>>(
+        config: OpenConfig,
+        items: impl ExactSizeIterator<Item = (KR, VR)>
+    ) -> Result<Self, FromSortedIterError>
+    where [(); Node::<I, K, V, S>::LEN]: {
+        let mut value = Self::open(config).map_err(FromSortedIterError::Open)?;
+        value.bulk_load(items).map_err(FromSortedIterError::BulkLoad)?;
+        Ok(value)
+    }
+
+    unsafe fn node_buf_by_id(&self, id: u64) -> binbuf::BufConst<Node<I, K, V, S>> {
         self.nodes.buf_unchecked(id)
     }
 
-    unsafe fn node_buf_mut_by_id(&mut self, id: u64) -> binbuf::BufMut<Node<I, K, V>> {
+    unsafe fn node_buf_mut_by_id(&mut self, id: u64) -> binbuf::BufMut<Node<I, K, V, S>> {
         self.nodes.buf_mut_unchecked(id)
     }
 
-    pub fn search(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Searched {
-        // let mut arr = [0u8; T::LEN];
-        // let key_buf = unsafe { T::buf(bytes_ptr::Mut::from_slice(&mut arr)) };
-        // key.write_to(key_buf);
-        let mut parent = None;
-        let Some(mut node_id) = self.root_id else {
-            return Searched { id: None, parent: None };
+    fn child_id(&self, id: u64, branch: NodeBranch) -> Option<u64> {
+        let node = unsafe { self.node_buf_by_id(id) };
+        let child_id = match branch {
+            NodeBranch::Left => binbuf::fixed::decode::<I, _>(Node::<I, K, V, S>::buf_left_id(node)),
+            NodeBranch::Right => binbuf::fixed::decode::<I, _>(Node::<I, K, V, S>::buf_right_id(node)),
+        };
+        child_id.to_u64().checked_sub(1)
+    }
+
+    fn set_child_id(&mut self, id: u64, branch: NodeBranch, child_id: Option<u64>) {
+        let node = unsafe { self.node_buf_mut_by_id(id) };
+        let child_id = I::from_u64(child_id.map_or(0, |id| id + 1));
+        match branch {
+            NodeBranch::Left => child_id.encode(Node::<I, K, V, S>::buf_left_id(node)),
+            NodeBranch::Right => child_id.encode(Node::<I, K, V, S>::buf_right_id(node)),
+        }
+    }
+
+    fn height(&self, id: Option<u64>) -> u8 {
+        match id {
+            Some(id) => binbuf::fixed::decode::<u8, _>(Node::<I, K, V, S>::buf_height(unsafe { self.node_buf_by_id(id) })),
+            None => 0,
+        }
+    }
+
+    fn size(&self, id: Option<u64>) -> u64 {
+        match id {
+            Some(id) => binbuf::fixed::decode::<S, _>(Node::<I, K, V, S>::buf_size(unsafe { self.node_buf_by_id(id) })).to_u64(),
+            None => 0,
+        }
+    }
+
+    // Updates height and size of the node from its children.
+    fn update_node(&mut self, id: u64) {
+        let left_id = self.child_id(id, NodeBranch::Left);
+        let right_id = self.child_id(id, NodeBranch::Right);
+        let height = self.height(left_id).max(self.height(right_id)) + 1;
+        let size = S::from_u64(self.size(left_id) + self.size(right_id) + 1);
+        let node = unsafe { self.node_buf_mut_by_id(id) };
+        height.encode(Node::<I, K, V, S>::buf_height(node));
+        size.encode(Node::<I, K, V, S>::buf_size(node));
+    }
+
+    // Moves node down towards the branch, its child from the opposite branch takes its place.
+    // Returns id of the node that took the place.
+    fn rotate(&mut self, id: u64, branch: NodeBranch) -> u64 {
+        let child_id = self.child_id(id, branch.opposite()).expect("Rotated node must have a child");
+        let grandchild_id = self.child_id(child_id, branch);
+        self.set_child_id(id, branch.opposite(), grandchild_id);
+        self.set_child_id(child_id, branch, Some(id));
+        self.update_node(id);
+        self.update_node(child_id);
+        child_id
+    }
+
+    // Updates the node and rotates it if its subtrees' heights differ by more than one.
+    // Returns id of the node that took its place.
+    fn rebalance(&mut self, id: u64) -> u64 {
+        self.update_node(id);
+        let left_height = self.height(self.child_id(id, NodeBranch::Left));
+        let right_height = self.height(self.child_id(id, NodeBranch::Right));
+        let heavy = if left_height > right_height + 1 {
+            NodeBranch::Left
+        } else if right_height > left_height + 1 {
+            NodeBranch::Right
+        } else {
+            return id;
         };
-        loop {
-            let node = unsafe { self.node_buf_by_id(node_id) };
-            match key.clone().buf_cmp(Node::buf_key(node)) {
-                std::cmp::Ordering::Less => {
-                    let left_id = binbuf::fixed::decode::<I, _>(Node::buf_left_id(node)).to_u64();
-                    parent = Some(NodeParent { id: node_id, branch: NodeBranch::Left });
-                    if left_id == 0 {
-                        return Searched { id: None, parent };
-                        // return Err(Some((node_id, NodeBranch::Left)));
-                    } else {
-                        // parent = Some((node_id, NodeBranch::Left));
-                        node_id = left_id - 1;
-                    }
-                },
-                std::cmp::Ordering::Equal => {
-                    return Searched { parent, id: Some(node_id) };
-                },
-                std::cmp::Ordering::Greater => {
-                    let right_id = binbuf::fixed::decode::<I, _>(Node::buf_right_id(node)).to_u64();
-                    parent = Some(NodeParent { id: node_id, branch: NodeBranch::Right });
-                    if right_id == 0 {
-                        return Searched { id: None, parent };
-                        // return Err(Some((node_id, NodeBranch::Right)));
-                    } else {
-                        node_id = right_id - 1;
-                    }
-                },
+        let heavy_id = self.child_id(id, heavy).expect("Heavy branch must have a child");
+        if self.height(self.child_id(heavy_id, heavy)) < self.height(self.child_id(heavy_id, heavy.opposite())) {
+            let new_heavy_id = self.rotate(heavy_id, heavy);
+            self.set_child_id(id, heavy, Some(new_heavy_id));
+        }
+        self.rotate(id, heavy.opposite())
+    }
+
+    // Sets id as the child of parent, or as the root if there is no parent.
+    fn link(&mut self, parent: Option<NodeParent>, id: Option<u64>) {
+        match parent {
+            Some(parent) => self.set_child_id(parent.id, parent.branch, id),
+            None => self.set_root_id(id),
+        }
+    }
+
+    // Rebalances every node of the path, starting from the bottom.
+    fn retrace(&mut self, path: &[NodeParent]) {
+        for idx in (0 .. path.len()).rev() {
+            let id = self.rebalance(path[idx].id);
+            if id != path[idx].id {
+                self.link(path[.. idx].last().copied(), Some(id));
+            }
+        }
+    }
+
+    // Filter isn't used, because the path is needed to change the tree even if the key doesn't exist.
+    // Keys of a filtered tree are equal only by their bytes, so the filter never disagrees with the search.
+    pub fn search(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Searched {
+        self.search_by(|key_buf, _| key.clone().buf_cmp(key_buf))
+    }
+
+    // Finds id of the node without recording the path, it's enough for lookups which don't change the tree.
+    fn find_id(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Option<u64> {
+        let mut node_id = self.root_id;
+        while let Some(id) = node_id {
+            node_id = match self.key_cmp(key.clone(), id) {
+                Ordering::Less => self.child_id(id, NodeBranch::Left),
+                Ordering::Equal => return Some(id),
+                Ordering::Greater => self.child_id(id, NodeBranch::Right),
+            };
+        }
+        None
+    }
+
+    // Same as `search`, but the function tells how the searched item is ordered relative to the node.
+    pub fn search_by(&self, mut cmp: impl FnMut(binbuf::BufConst<K>, binbuf::BufConst<V>) -> Ordering) -> Searched {
+        let mut path = Vec::new();
+        let mut node_id = self.root_id;
+        while let Some(id) = node_id {
+            let node = unsafe { self.node_buf_by_id(id) };
+            let branch = match cmp(Node::<I, K, V, S>::buf_key(node), Node::<I, K, V, S>::buf_value(node)) {
+                Ordering::Less => NodeBranch::Left,
+                Ordering::Equal => return Searched { path, id: Some(id) },
+                Ordering::Greater => NodeBranch::Right,
+            };
+            path.push(NodeParent { id, branch });
+            node_id = self.child_id(id, branch);
+        }
+        Searched { path, id: None }
+    }
+
+    // Searches the key once, the returned entry can then be used to change the tree in place.
+    pub fn entry<Q: binbuf::fixed::BufOrd<K> + Clone>(&mut self, key: Q) -> search::Value<'_, I, K, V, S, Q> {
+        search::Value::new(self, key)
+    }
+
+    fn item(&self, id: u64) -> (binbuf::BufConst<K>, binbuf::BufConst<V>) {
+        let node = unsafe { self.node_buf_by_id(id) };
+        (Node::<I, K, V, S>::buf_key(node), Node::<I, K, V, S>::buf_value(node))
+    }
+
+    fn key_cmp(&self, key: impl binbuf::fixed::BufOrd<K>, id: u64) -> Ordering {
+        key.buf_cmp(Node::<I, K, V, S>::buf_key(unsafe { self.node_buf_by_id(id) }))
+    }
+
+    // Whether key of the node isn't before the start bound.
+    fn is_after_start<Q: binbuf::fixed::BufOrd<K> + Clone>(&self, id: u64, start: Bound<&Q>) -> bool {
+        match start {
+            Bound::Included(key) => self.key_cmp(key.clone(), id) != Ordering::Greater,
+            Bound::Excluded(key) => self.key_cmp(key.clone(), id) == Ordering::Less,
+            Bound::Unbounded => true,
+        }
+    }
+
+    // Whether key of the node isn't after the end bound.
+    fn is_before_end<Q: binbuf::fixed::BufOrd<K> + Clone>(&self, id: u64, end: Bound<&Q>) -> bool {
+        match end {
+            Bound::Included(key) => self.key_cmp(key.clone(), id) != Ordering::Less,
+            Bound::Excluded(key) => self.key_cmp(key.clone(), id) == Ordering::Greater,
+            Bound::Unbounded => true,
+        }
+    }
+
+    // Pushes the node and all of its descendants towards the branch.
+    fn push_branch(&self, stack: &mut Vec<u64>, mut node_id: Option<u64>, branch: NodeBranch) {
+        while let Some(id) = node_id {
+            stack.push(id);
+            node_id = self.child_id(id, branch);
+        }
+    }
+
+    // Stack for iterating forward, the first node after the start bound is on top.
+    fn front_stack<Q: binbuf::fixed::BufOrd<K> + Clone>(&self, start: Bound<&Q>) -> Vec<u64> {
+        let mut stack = Vec::new();
+        let mut node_id = self.root_id;
+        while let Some(id) = node_id {
+            if self.is_after_start(id, start) {
+                stack.push(id);
+                node_id = self.child_id(id, NodeBranch::Left);
+            } else {
+                node_id = self.child_id(id, NodeBranch::Right);
             }
         }
+        stack
+    }
+
+    // Stack for iterating backward, the last node before the end bound is on top.
+    fn back_stack<Q: binbuf::fixed::BufOrd<K> + Clone>(&self, end: Bound<&Q>) -> Vec<u64> {
+        let mut stack = Vec::new();
+        let mut node_id = self.root_id;
+        while let Some(id) = node_id {
+            if self.is_before_end(id, end) {
+                stack.push(id);
+                node_id = self.child_id(id, NodeBranch::Right);
+            } else {
+                node_id = self.child_id(id, NodeBranch::Left);
+            }
+        }
+        stack
+    }
+
+    pub fn iter(&self) -> Iter<'_, I, K, V, S> {
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        self.push_branch(&mut front, self.root_id, NodeBranch::Left);
+        self.push_branch(&mut back, self.root_id, NodeBranch::Right);
+        Iter { tree: self, front, back }
+    }
+
+    pub fn rev(&self) -> std::iter::Rev<Iter<'_, I, K, V, S>> {
+        self.iter().rev()
+    }
+
+    pub fn range<Q: binbuf::fixed::BufOrd<K> + Clone>(&self, range: impl RangeBounds<Q>) -> Iter<'_, I, K, V, S> {
+        let front = self.front_stack(range.start_bound());
+        let back = self.back_stack(range.end_bound());
+        // Range is empty if its first node is already after the end bound.
+        match front.last() {
+            Some(&id) if self.is_before_end(id, range.end_bound()) => Iter { tree: self, front, back },
+            _ => Iter { tree: self, front: Vec::new(), back: Vec::new() },
+        }
+    }
+
+    pub fn first(&self) -> Option<(binbuf::BufConst<K>, binbuf::BufConst<V>)> {
+        self.iter().next()
+    }
+
+    pub fn last(&self) -> Option<(binbuf::BufConst<K>, binbuf::BufConst<V>)> {
+        self.iter().next_back()
+    }
+
+    // Bounds follow the C++ convention: `lower_bound` is the first item not before the key, `upper_bound` is the first item after it.
+    // First item with key greater than or equal to the given one.
+    pub fn lower_bound(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Option<(binbuf::BufConst<K>, binbuf::BufConst<V>)> {
+        self.front_stack(Bound::Included(&key)).last().map(|&id| self.item(id))
+    }
+
+    // First item with key greater than the given one.
+    pub fn upper_bound(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Option<(binbuf::BufConst<K>, binbuf::BufConst<V>)> {
+        self.front_stack(Bound::Excluded(&key)).last().map(|&id| self.item(id))
+    }
+
+    // Last item with key less than or equal to the given one.
+    pub fn floor(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Option<(binbuf::BufConst<K>, binbuf::BufConst<V>)> {
+        self.back_stack(Bound::Included(&key)).last().map(|&id| self.item(id))
+    }
+
+    // Last item with key less than the given one.
+    pub fn predecessor(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Option<(binbuf::BufConst<K>, binbuf::BufConst<V>)> {
+        self.back_stack(Bound::Excluded(&key)).last().map(|&id| self.item(id))
+    }
+
+    // First item with key greater than the given one, same as `upper_bound`.
+    pub fn successor(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Option<(binbuf::BufConst<K>, binbuf::BufConst<V>)> {
+        self.upper_bound(key)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn set_len(&mut self, len: u64) {
+        len.encode(Header::buf_len(self.header.buf_mut()));
+        self.len = len;
+    }
+
+    // Walks the whole tree to measure its shape.
+    pub fn stats(&self) -> Stats {
+        let mut depth_sum = 0;
+        let mut stack = Vec::from_iter(self.root_id.map(|id| (id, 1)));
+        while let Some((id, depth)) = stack.pop() {
+            depth_sum += depth;
+            stack.extend(self.child_id(id, NodeBranch::Left).map(|id| (id, depth + 1)));
+            stack.extend(self.child_id(id, NodeBranch::Right).map(|id| (id, depth + 1)));
+        }
+        Stats {
+            len: self.len,
+            depth: self.height(self.root_id),
+            average_depth: if self.len == 0 { 0. } else { depth_sum as f64 / self.len as f64 },
+            free_ids: self.free_ids.len(),
+            utilisation: if self.nodes.is_empty() { 1. } else { self.len as f64 / self.nodes.len() as f64 },
+        }
     }
 
     fn set_root_id(&mut self, id: Option<u64>) {
@@ -239,6 +642,10 @@ impl<I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed> Value<I, K,
         self.root_id = id;
     }
 
+    pub unsafe fn key_buf_searched(&self, searched: &SearchedFound) -> binbuf::BufConst<K> {
+        Node::buf_key(unsafe { self.node_buf_by_id(searched.id) })
+    }
+
     pub unsafe fn buf_searched(&self, searched: &SearchedFound) -> binbuf::BufConst<V> {
         Node::buf_value(unsafe { self.node_buf_by_id(searched.id) })
     }
@@ -248,62 +655,90 @@ impl<I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed> Value<I, K,
     }
 
     pub fn buf(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Option<binbuf::BufConst<V>> {
-        self.search(key).find().ok().map(|s| unsafe { self.buf_searched(&s) })
+        if !self.may_contain(key.clone()) {
+            return None;
+        }
+        self.find_id(key).map(|id| Node::buf_value(unsafe { self.node_buf_by_id(id) }))
     }
 
     pub fn buf_mut(&mut self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Option<binbuf::BufMut<V>> {
-        self.search(key).find().ok().map(|s| unsafe { self.buf_mut_searched(&s) })
+        if !self.may_contain(key.clone()) {
+            return None;
+        }
+        self.find_id(key).map(|id| Node::buf_value(unsafe { self.node_buf_mut_by_id(id) }))
+    }
+
+    pub fn contains_key(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> bool {
+        self.may_contain(key.clone()) && self.find_id(key).is_some()
+    }
+
+    fn add_node(&mut self, node: impl binbuf::fixed::Readable<Node<I, K, V, S>>) -> Result<u64, AddError> {
+        match self.free_ids.last_buf() {
+            Some(id_buf) => {
+                let id = binbuf::fixed::decode::<u64, _>(id_buf);
+                node.write_to(unsafe { self.node_buf_mut_by_id(id) });
+                self.free_ids.remove_last().map_err(AddError::RemoveLastFreeId)?;
+                Ok(id)
+            },
+            None => self.nodes.add(node).map_err(AddError::AddNode),
+        }
+    }
+
+    fn write_node(
+        node_buf: binbuf::BufMut<Node<I, K, V, S>>,
+        key: impl binbuf::fixed::Readable<K>,
+        value: impl binbuf::fixed::Readable<V>,
+        left_id: Option<u64>,
+        right_id: Option<u64>,
+        height: u8,
+        size: u64
+    ) {
+        key.write_to(Node::<I, K, V, S>::buf_key(node_buf));
+        value.write_to(Node::<I, K, V, S>::buf_value(node_buf));
+        I::from_u64(left_id.map_or(0, |id| id + 1)).encode(Node::<I, K, V, S>::buf_left_id(node_buf));
+        I::from_u64(right_id.map_or(0, |id| id + 1)).encode(Node::<I, K, V, S>::buf_right_id(node_buf));
+        height.encode(Node::<I, K, V, S>::buf_height(node_buf));
+        S::from_u64(size).encode(Node::<I, K, V, S>::buf_size(node_buf));
+    }
+
+    // Adds node at the end of the path and rebalances the tree.
+    // Returns id of the new node.
+    fn insert_node(
+        &mut self,
+        path: &[NodeParent],
+        key: impl binbuf::fixed::Readable<K>,
+        value: impl binbuf::fixed::Readable<V>
+    ) -> Result<u64, AddError>
+    where [(); Node::<I, K, V, S>::LEN]: {
+        let mut node_arr = [0u8; Node::<I, K, V, S>::LEN];
+        let node_buf = unsafe { Node::<I, K, V, S>::buf(bytes_ptr::Mut::from_slice(&mut node_arr)) };
+        Self::write_node(node_buf, key, value, None, None, 1, 1);
+
+        // Key is added to the filter first, if adding the node fails it only makes a false positive.
+        if let Some(bloom) = &mut self.bloom {
+            bloom.add_buf(Node::<I, K, V, S>::buf_key(binbuf::buf_to_const::<Node<I, K, V, S>, _>(node_buf))).map_err(AddError::AddToBloom)?;
+        }
+        let id = self.add_node(node_buf)?;
+        self.link(path.last().copied(), Some(id));
+        self.retrace(path);
+        self.set_len(self.len + 1);
+        Ok(id)
     }
 
-    // Returns true if item already exists.
     pub unsafe fn add_searched(
         &mut self,
         search: &SearchedNotFound,
-        key: impl binbuf::fixed::BufOrd<K> + Clone,
+        key: impl binbuf::fixed::Readable<K>,
         value: impl binbuf::fixed::Readable<V>
     ) -> Result<(), AddError>
-    where [(); Node::<I, K, V>::LEN]: {
-        let mut node_arr = [0u8; Node::<I, K, V>::LEN];
-        let node_buf = unsafe { Node::buf(bytes_ptr::Mut::from_slice(&mut node_arr)) };
-        key.clone().write_to(Node::<I, K, V>::buf_key(node_buf));
-        value.write_to(Node::<I, K, V>::buf_value(node_buf));
-        I::from_u64(0u64).encode(Node::<I, K, V>::buf_left_id(node_buf));
-        I::from_u64(0u64).encode(Node::<I, K, V>::buf_right_id(node_buf));
-
-        match search.parent {
-            None => {
-                let id = self.nodes.add(node_buf).map_err(AddError::AddNode)?;
-                self.set_root_id(Some(id));
-            }
-            Some(parent) => {
-                let id = match self.free_ids.last_buf() {
-                    Some(id_buf) => {
-                        let id = binbuf::fixed::decode::<u64, _>(id_buf);
-                        node_buf.write_to(unsafe { self.node_buf_mut_by_id(id) });
-                        self.free_ids.remove_last().map_err(AddError::RemoveLastFreeId)?;
-                        id
-                    },
-                    None => {
-                        self.nodes.add(node_buf).map_err(AddError::AddNode)?
-                    }
-                };
-
-                let parent_buf = unsafe { self.node_buf_mut_by_id(parent.id) };
-                match parent.branch {
-                    NodeBranch::Left => {
-                        I::from_u64(id + 1).write_to(Node::<I, K, V>::buf_left_id(parent_buf));
-                    },
-                    NodeBranch::Right => {
-                        I::from_u64(id + 1).write_to(Node::<I, K, V>::buf_right_id(parent_buf));
-                    }
-                }
-            }
-        }
+    where [(); Node::<I, K, V, S>::LEN]: {
+        self.insert_node(&search.path, key, value)?;
         Ok(())
     }
 
+    // Returns true if item already exists.
     pub fn add(&mut self, key: impl binbuf::fixed::BufOrd<K> + Clone, value: impl binbuf::fixed::Readable<V>) -> Result<bool, AddError>
-    where [(); Node::<I, K, V>::LEN]: {
+    where [(); Node::<I, K, V, S>::LEN]: {
         match self.search(key.clone()).find() {
             Ok(_) => Ok(true),
             Err(s) => {
@@ -313,6 +748,89 @@ impl<I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed> Value<I, K,
         }
     }
 
+    // Builds a perfectly balanced tree from items sorted by key, without any comparisons or rotations.
+    // Tree must be empty, and keys must be sorted and unique, which isn't checked.
+    // If the iterator returns a different count of items than its length, added nodes are removed and the tree stays empty.
+    pub fn bulk_load<KR: binbuf::fixed::Readable<K>, VR: binbuf::fixed::Readable<V>>(
+        &mut self,
+        mut items: impl ExactSizeIterator<Item = (KR, VR)>
+    ) -> Result<(), BulkLoadError>
+    where [(); Node::<I, K, V, S>::LEN]: {
+        if !self.nodes.is_empty() {
+            return Err(BulkLoadError::NodesNotEmpty);
+        }
+        if !self.free_ids.is_empty() {
+            return Err(BulkLoadError::FreeIdsNotEmpty);
+        }
+        let len = items.len() as u64;
+        self.nodes.reserve(len).map_err(BulkLoadError::ReserveNodes)?;
+        let loaded = self.bulk_load_range(&mut items, 0, len).and_then(|()| match items.next() {
+            Some(_) => Err(BulkLoadError::WrongLen),
+            None => Ok(()),
+        }).and_then(|()| self.bulk_load_bloom());
+        if let Err(error) = loaded {
+            while !self.nodes.is_empty() {
+                self.nodes.remove_last().map_err(BulkLoadError::RemoveNode)?;
+            }
+            return Err(error);
+        }
+        // Header is written only after everything else succeeded, so a failed load leaves it untouched.
+        self.set_root_id(Self::bulk_load_root_id(0, len));
+        self.set_len(len);
+        Ok(())
+    }
+
+    // Bits can't be cleared from the filter, so keys are added only once all nodes are loaded.
+    // If it fails, bits of the removed nodes are left, which only makes false positives.
+    fn bulk_load_bloom(&mut self) -> Result<(), BulkLoadError> {
+        if let Some(bloom) = &mut self.bloom {
+            for id in self.nodes.all_ids() {
+                bloom.add_buf(Node::<I, K, V, S>::buf_key(unsafe { self.nodes.buf_unchecked(id) })).map_err(BulkLoadError::AddToBloom)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Nodes are written in order of keys, so id of each node is its position among the items.
+    // Middle item of the range is the root of its subtree.
+    fn bulk_load_root_id(start: u64, end: u64) -> Option<u64> {
+        (start < end).then(|| start + (end - start) / 2)
+    }
+
+    // Height of a subtree built from the count of items.
+    fn bulk_load_height(len: u64) -> u8 {
+        (u64::BITS - len.leading_zeros()) as u8
+    }
+
+    fn bulk_load_range<KR: binbuf::fixed::Readable<K>, VR: binbuf::fixed::Readable<V>>(
+        &mut self,
+        items: &mut impl Iterator<Item = (KR, VR)>,
+        start: u64,
+        end: u64
+    ) -> Result<(), BulkLoadError>
+    where [(); Node::<I, K, V, S>::LEN]: {
+        let Some(id) = Self::bulk_load_root_id(start, end) else {
+            return Ok(());
+        };
+        self.bulk_load_range(items, start, id)?;
+
+        let (key, value) = items.next().ok_or(BulkLoadError::WrongLen)?;
+        let mut node_arr = [0u8; Node::<I, K, V, S>::LEN];
+        let node_buf = unsafe { Node::<I, K, V, S>::buf(bytes_ptr::Mut::from_slice(&mut node_arr)) };
+        Self::write_node(
+            node_buf,
+            key,
+            value,
+            Self::bulk_load_root_id(start, id),
+            Self::bulk_load_root_id(id + 1, end),
+            Self::bulk_load_height(end - start),
+            end - start
+        );
+        self.nodes.add(node_buf).map_err(BulkLoadError::AddNode)?;
+
+        self.bulk_load_range(items, id + 1, end)
+    }
+
     fn remove_node(&mut self, id: u64) -> Result<(), RemoveNodeError> {
         if self.nodes.remove_if_last(id).map_err(RemoveNodeError::RemoveIfLast)? {
             self.free_ids.add(&id).map_err(RemoveNodeError::AddFreeId)?;
@@ -320,96 +838,48 @@ impl<I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed> Value<I, K,
         Ok(())
     }
 
-    // Returns true if item doesn't exist.
     pub unsafe fn remove_searched(&mut self, searched: &SearchedFound) -> Result<(), RemoveError> {
-        let node_buf: NodeBuf<bytes_ptr::Mut, I, K, V> = unsafe { self.node_buf_mut_by_id(searched.id) };
-        let left_id = binbuf::fixed::decode::<I, _>(Node::buf_left_id(node_buf)).to_u64();
-        let right_id = binbuf::fixed::decode::<I, _>(Node::buf_right_id(node_buf)).to_u64();
-        
-        match (left_id, right_id, searched.parent) {
-            (_, _, Some(parent)) if left_id == 0 || right_id == 0 => {
-                self.remove_node(searched.id).map_err(RemoveError::RemoveNode)?;
-
-                let connect_id = if left_id == 0 { right_id } else { left_id };
-                let mut parent_buf = unsafe { self.node_buf_mut_by_id(parent.id) };
-                match parent.branch {
-                    NodeBranch::Left => {
-                        I::from_u64(connect_id).encode(Node::<I, K, V>::buf_left_id(parent_buf));
-                    }
-                    NodeBranch::Right => {
-                        I::from_u64(connect_id).encode(Node::<I, K, V>::buf_right_id(parent_buf));
-                    }
-                }
-            },
-            (0, 0, None) => {
-                self.remove_node(searched.id).map_err(RemoveError::RemoveNode)?;
-                self.set_root_id(None);
-            },
-            (_, _, None) if left_id == 0 || right_id == 0 => {
-                self.remove_node(searched.id).map_err(RemoveError::RemoveNode)?;
-
-                let root_id = if left_id == 0 { right_id } else { left_id } - 1;
-                self.set_root_id(Some(root_id));
-            },
+        let left_id = self.child_id(searched.id, NodeBranch::Left);
+        let right_id = self.child_id(searched.id, NodeBranch::Right);
+        let parent = searched.path.last().copied();
 
+        match (left_id, right_id) {
             // The most complex case to handle: both left and right branches exist.
-            (_, _, parent) => {
-                debug_assert_ne!(left_id, 0);
-                debug_assert_ne!(right_id, 0);
-
-                self.remove_node(searched.id).map_err(RemoveError::RemoveNode)?;
-
-                let mut node_parent_id = searched.id;
-                let mut node_id = right_id - 1;
-                let mut idx = 0;
-                loop {
-                    idx += 1;
-                    if idx > 100 {
-                        panic!("Loop stuck!");
-                    }
-
-                    let node_buf = unsafe { self.node_buf_mut_by_id(node_id) };
-                    let node_left_id_buf = Node::<I, K, V>::buf_left_id(node_buf);
-                    let node_left_id = binbuf::fixed::decode::<I, _>(node_left_id_buf).to_u64();
-                    let node_right_id_buf = Node::<I, K, V>::buf_right_id(node_buf);
-                    let node_right_id = binbuf::fixed::decode::<I, _>(node_right_id_buf).to_u64();
-                    if node_left_id == 0 {
-                        I::from_u64(node_right_id)
-                            .write_to(Node::buf_left_id(unsafe { self.node_buf_mut_by_id(node_parent_id) }));
-
-                        I::from_u64(left_id).write_to(node_left_id_buf);
-                        if searched.id != node_parent_id {
-                            I::from_u64(right_id).write_to(node_right_id_buf);
-                        }
-
-                        match parent {
-                            Some(parent) => {
-                                let parent_buf = unsafe { self.node_buf_mut_by_id(parent.id) };
-                                match parent.branch {
-                                    NodeBranch::Left => {
-                                        I::from_u64(node_id + 1).encode(Node::<I, K, V>::buf_left_id(parent_buf));
-                                    }
-                                    NodeBranch::Right => {
-                                        I::from_u64(node_id + 1).encode(Node::<I, K, V>::buf_right_id(parent_buf));
-                                    }
-                                }
-                            },
-                            None => {
-                                self.set_root_id(Some(node_id));
-                            }
-                        }
-
-                        break;
-                    } else {
-                        node_parent_id = node_id;
-                        node_id = node_left_id - 1;
-                    }
+            // Node is replaced by its successor, which is the leftmost node of the right branch.
+            (Some(left_id), Some(right_id)) => {
+                let mut successor_path = Vec::new();
+                let mut successor_id = right_id;
+                while let Some(id) = self.child_id(successor_id, NodeBranch::Left) {
+                    successor_path.push(NodeParent { id: successor_id, branch: NodeBranch::Left });
+                    successor_id = id;
+                }
+
+                // If successor is the right child itself, it keeps its right branch.
+                if let Some(successor_parent) = successor_path.last().copied() {
+                    let successor_right_id = self.child_id(successor_id, NodeBranch::Right);
+                    self.link(Some(successor_parent), successor_right_id);
+                    self.set_child_id(successor_id, NodeBranch::Right, Some(right_id));
                 }
+                self.set_child_id(successor_id, NodeBranch::Left, Some(left_id));
+                self.link(parent, Some(successor_id));
+
+                let mut path = searched.path.clone();
+                path.push(NodeParent { id: successor_id, branch: NodeBranch::Right });
+                path.extend(successor_path);
+                self.retrace(&path);
+            },
+            _ => {
+                self.link(parent, left_id.or(right_id));
+                self.retrace(&searched.path);
             }
         }
+
+        self.remove_node(searched.id).map_err(RemoveError::RemoveNode)?;
+        self.set_len(self.len - 1);
         Ok(())
     }
 
+    // Returns true if item doesn't exist.
     pub fn remove(&mut self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Result<bool, RemoveError> {
         match self.search(key).find() {
             Ok(s) => {
@@ -421,9 +891,48 @@ impl<I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed> Value<I, K,
     }
 }
 
-impl<I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::fixed::Decode> Value<I, K, V> {
+// Only available when subtree sizes are tracked.
+impl<I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed, S: TracksSize> Value<I, K, V, S> {
+    // Item at the position in order of keys, starting from 0.
+    pub fn nth(&self, mut idx: u64) -> Option<(binbuf::BufConst<K>, binbuf::BufConst<V>)> {
+        let mut node_id = self.root_id;
+        while let Some(id) = node_id {
+            let left_id = self.child_id(id, NodeBranch::Left);
+            let left_size = self.size(left_id);
+            node_id = match idx.cmp(&left_size) {
+                Ordering::Less => left_id,
+                Ordering::Equal => return Some(self.item(id)),
+                Ordering::Greater => {
+                    idx -= left_size + 1;
+                    self.child_id(id, NodeBranch::Right)
+                }
+            };
+        }
+        None
+    }
+
+    // Count of items with keys less than the given one.
+    pub fn rank(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> u64 {
+        let mut rank = 0;
+        let mut node_id = self.root_id;
+        while let Some(id) = node_id {
+            let left_id = self.child_id(id, NodeBranch::Left);
+            node_id = match self.key_cmp(key.clone(), id) {
+                Ordering::Less => left_id,
+                Ordering::Equal => return rank + self.size(left_id),
+                Ordering::Greater => {
+                    rank += self.size(left_id) + 1;
+                    self.child_id(id, NodeBranch::Right)
+                }
+            };
+        }
+        rank
+    }
+}
+
+impl<I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::fixed::Decode, S: NodeSize> Value<I, K, V, S> {
     pub fn get(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> Option<V> {
-        self.search(key).find().ok().map(|s| unsafe { self.get_searched(&s) })
+        self.buf(key).map(|buf| binbuf::fixed::decode::<V, _>(buf))
     }
 
     pub unsafe fn get_searched(&self, searched: &SearchedFound) -> V {
@@ -1,30 +1,116 @@
-use super::{NodeId, NodeParent};
+use std::fmt::Debug;
+use binbuf::{fixed::Readable, Fixed as _};
+use super::{AddError, Node, NodeId, NodeSize, RemoveError, SearchedFound, SearchedNotFound};
 
-pub struct Value<'a, I: NodeId, K, V> {
-    parent: Option<NodeParent>,
-    id: Option<u64>,
-    handle: &'a mut super::Value<I, K, V>
+// Result of a single search, which keeps the tree borrowed so that it can be changed without searching again.
+pub enum Value<'a, I: NodeId, K, V, S: NodeSize, Q> {
+    Occupied(Found<'a, I, K, V, S>),
+    Vacant(NotFound<'a, I, K, V, S, Q>),
 }
 
-impl<'a, I: NodeId, K, V> Value<'a, I, K, V> {
-    
+impl<'a, I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed, S: NodeSize, Q: binbuf::fixed::BufOrd<K> + Clone> Value<'a, I, K, V, S, Q> {
+    pub(super) fn new(handle: &'a mut super::Value<I, K, V, S>, key: Q) -> Self {
+        match handle.search(key.clone()).find() {
+            Ok(searched) => Self::Occupied(Found { searched, handle }),
+            Err(searched) => Self::Vacant(NotFound { searched, key, handle }),
+        }
+    }
+
+    pub fn is_occupied(&self) -> bool {
+        matches!(self, Self::Occupied(_))
+    }
+
+    pub fn or_insert(self, value: impl Readable<V>) -> Result<binbuf::BufMut<V>, AddError>
+    where [(); Node::<I, K, V, S>::LEN]: {
+        match self {
+            Self::Occupied(found) => Ok(found.into_buf_mut()),
+            Self::Vacant(not_found) => not_found.insert(value),
+        }
+    }
+
+    pub fn and_modify(mut self, f: impl FnOnce(binbuf::BufMut<V>)) -> Self {
+        if let Self::Occupied(found) = &mut self {
+            f(found.buf_mut());
+        }
+        self
+    }
 }
 
-pub struct Found<'a, I: NodeId, K, V> {
-    parent: Option<NodeParent>,
-    id: u64,
-    handle: &'a mut super::Value<I, K, V>
+impl<'a, I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::fixed::Decode, S: NodeSize, Q: binbuf::fixed::BufOrd<K> + Clone> Value<'a, I, K, V, S, Q> {
+    // Returns the previous value if item already exists.
+    pub fn insert(self, value: impl Readable<V>) -> Result<Option<V>, AddError>
+    where [(); Node::<I, K, V, S>::LEN]: {
+        match self {
+            Self::Occupied(mut found) => Ok(Some(found.insert(value))),
+            Self::Vacant(not_found) => {
+                not_found.insert(value)?;
+                Ok(None)
+            }
+        }
+    }
 }
 
-impl<'a, I: NodeId, K, V> Found<'a, I, K, V> {
+pub struct Found<'a, I: NodeId, K, V, S: NodeSize> {
+    searched: SearchedFound,
+    handle: &'a mut super::Value<I, K, V, S>
+}
+
+impl<'a, I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed, S: NodeSize> Found<'a, I, K, V, S> {
+    pub fn key_buf(&self) -> binbuf::BufConst<K> {
+        Node::<I, K, V, S>::buf_key(unsafe { self.handle.node_buf_by_id(self.searched.id) })
+    }
+
+    pub fn buf(&self) -> binbuf::BufConst<V> {
+        unsafe { self.handle.buf_searched(&self.searched) }
+    }
+
+    pub fn buf_mut(&mut self) -> binbuf::BufMut<V> {
+        unsafe { self.handle.buf_mut_searched(&self.searched) }
+    }
 
+    pub fn into_buf_mut(self) -> binbuf::BufMut<V> {
+        unsafe { self.handle.buf_mut_searched(&self.searched) }
+    }
+
+    pub fn remove(self) -> Result<(), RemoveError> {
+        unsafe { self.handle.remove_searched(&self.searched) }
+    }
+}
+
+impl<'a, I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::fixed::Decode, S: NodeSize> Found<'a, I, K, V, S> {
+    pub fn get(&self) -> V {
+        unsafe { self.handle.get_searched(&self.searched) }
+    }
+
+    // Returns the previous value.
+    pub fn insert(&mut self, value: impl Readable<V>) -> V {
+        let old_value = self.get();
+        value.write_to(self.buf_mut());
+        old_value
+    }
+
+    pub fn remove_entry(self) -> Result<(K, V), RemoveError> {
+        let entry = (binbuf::fixed::decode::<K, _>(self.key_buf()), self.get());
+        self.remove()?;
+        Ok(entry)
+    }
 }
 
-pub struct NotFound<'a, I: NodeId, K, V> {
-    parent: Option<NodeParent>,
-    handle: &'a mut super::Value<I, K, V>
+pub struct NotFound<'a, I: NodeId, K, V, S: NodeSize, Q> {
+    searched: SearchedNotFound,
+    key: Q,
+    handle: &'a mut super::Value<I, K, V, S>
 }
 
-impl<'a, I: NodeId, K, V> NotFound<'a, I, K, V> {
+impl<'a, I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed, S: NodeSize, Q: binbuf::fixed::BufOrd<K> + Clone> NotFound<'a, I, K, V, S, Q> {
+    pub fn key(&self) -> &Q {
+        &self.key
+    }
 
-}
\ No newline at end of file
+    // Returns buffer of the inserted value.
+    pub fn insert(self, value: impl Readable<V>) -> Result<binbuf::BufMut<V>, AddError>
+    where [(); Node::<I, K, V, S>::LEN]: {
+        let id = self.handle.insert_node(&self.searched.path, self.key, value)?;
+        Ok(Node::<I, K, V, S>::buf_value(unsafe { self.handle.node_buf_mut_by_id(id) }))
+    }
+}
@@ -0,0 +1,77 @@
+use std::{cmp::Ordering, fmt::Debug, ops::Bound};
+use binbuf::Fixed as _;
+use super::binary_tree::{Node, NodeId, Searched};
+
+pub use super::binary_tree::{AddError, OpenConfig, OpenError, OpenFiles, OpenMaxMargins, RemoveError};
+
+// Binary tree which can hold many values per key.
+// Nodes are ordered by key and then by value, so values of the same key are stored next to each other.
+pub struct Value<I: NodeId, K, V> {
+    tree: super::BinaryTree<I, K, V>,
+}
+
+impl<I: NodeId, K: binbuf::fixed::Decode + Debug, V: binbuf::Fixed> Value<I, K, V> {
+    pub unsafe fn open(config: OpenConfig) -> Result<Self, OpenError> {
+        Ok(Self {
+            tree: super::BinaryTree::open(config)?,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    fn search(&self, key: impl binbuf::fixed::BufOrd<K> + Clone, value: impl binbuf::fixed::BufOrd<V> + Clone) -> Searched {
+        self.tree.search_by(|key_buf, value_buf| {
+            key.clone().buf_cmp(key_buf).then_with(|| value.clone().buf_cmp(value_buf))
+        })
+    }
+
+    // Equal pairs are allowed too, new one is put after the existing ones.
+    pub fn insert(&mut self, key: impl binbuf::fixed::BufOrd<K> + Clone, value: impl binbuf::fixed::BufOrd<V> + Clone) -> Result<(), AddError>
+    where [(); Node::<I, K, V, ()>::LEN]: {
+        let searched = self.tree.search_by(|key_buf, value_buf| {
+            match key.clone().buf_cmp(key_buf).then_with(|| value.clone().buf_cmp(value_buf)) {
+                Ordering::Equal => Ordering::Greater,
+                ordering => ordering,
+            }
+        });
+        match searched.find() {
+            Ok(_) => unreachable!("Search never stops at an equal node"),
+            Err(s) => unsafe { self.tree.add_searched(&s, key, value) },
+        }
+    }
+
+    pub fn contains(&self, key: impl binbuf::fixed::BufOrd<K> + Clone, value: impl binbuf::fixed::BufOrd<V> + Clone) -> bool {
+        self.search(key, value).is_found()
+    }
+
+    pub fn contains_key(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> bool {
+        self.tree.lower_bound(key.clone()).is_some_and(|(key_buf, _)| key.buf_cmp(key_buf) == Ordering::Equal)
+    }
+
+    // Iterates values of the key in their order.
+    pub fn get_all(&self, key: impl binbuf::fixed::BufOrd<K> + Clone) -> impl DoubleEndedIterator<Item = binbuf::BufConst<V>> + '_ {
+        self.tree.range((Bound::Included(key.clone()), Bound::Included(key))).map(|(_, value_buf)| value_buf)
+    }
+
+    // Removes one of the equal pairs.
+    // Returns true if item doesn't exist.
+    pub fn remove(&mut self, key: impl binbuf::fixed::BufOrd<K> + Clone, value: impl binbuf::fixed::BufOrd<V> + Clone) -> Result<bool, RemoveError> {
+        match self.search(key, value).find() {
+            Ok(s) => {
+                unsafe { self.tree.remove_searched(&s) }?;
+                Ok(false)
+            },
+            Err(_) => Ok(true)
+        }
+    }
+
+    pub fn iter(&self) -> super::binary_tree::Iter<'_, I, K, V, ()> {
+        self.tree.iter()
+    }
+}
@@ -0,0 +1,112 @@
+use std::fs::File;
+use super::OpenMode;
+
+pub use super::fixed::OpenError;
+
+#[derive(Debug)]
+pub enum SetError {
+    AddWord(super::fixed::AddError),
+}
+
+const WORD_BITS: u64 = u64::BITS as u64;
+
+// Set of indices stored as bits packed into words.
+// Bits past the stored words are unset, words are added as bits get set.
+pub struct Value {
+    words: super::Fixed<u64>,
+}
+
+impl Value {
+    pub unsafe fn open(mode: OpenMode, file: File, max_margin: u64) -> Result<Self, OpenError> {
+        Ok(Self {
+            words: super::Fixed::open(mode, file, max_margin)?,
+        })
+    }
+
+    // Number of bits which can be set without adding words.
+    pub fn len(&self) -> u64 {
+        self.words.len() * WORD_BITS
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    fn word(&self, word_id: u64) -> u64 {
+        if self.words.is_id_valid(word_id) {
+            binbuf::fixed::decode::<u64, _>(unsafe { self.words.buf_unchecked(word_id) })
+        } else {
+            0
+        }
+    }
+
+    pub fn get(&self, idx: u64) -> bool {
+        self.word(idx / WORD_BITS) & (1 << (idx % WORD_BITS)) != 0
+    }
+
+    fn reserve_words(&mut self, len: u64) -> Result<(), SetError> {
+        while self.words.len() < len {
+            self.words.add(&0u64).map_err(SetError::AddWord)?;
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, idx: u64) -> Result<(), SetError> {
+        let word_id = idx / WORD_BITS;
+        self.reserve_words(word_id + 1)?;
+        self.words.set(word_id, &(self.word(word_id) | (1 << (idx % WORD_BITS))));
+        Ok(())
+    }
+
+    pub fn clear(&mut self, idx: u64) {
+        let word_id = idx / WORD_BITS;
+        if self.words.is_id_valid(word_id) {
+            self.words.set(word_id, &(self.word(word_id) & !(1 << (idx % WORD_BITS))));
+        }
+    }
+
+    pub fn count_ones(&self) -> u64 {
+        self.words.all_ids().map(|word_id| self.word(word_id).count_ones() as u64).sum()
+    }
+
+    // Returns the first set bit at the index or after it.
+    fn next_set_from(&self, idx: u64) -> Option<u64> {
+        let first_word_id = idx / WORD_BITS;
+        let mut word = self.word(first_word_id) & (u64::MAX << (idx % WORD_BITS));
+        for word_id in first_word_id .. self.words.len() {
+            if word_id != first_word_id {
+                word = self.word(word_id);
+            }
+            if word != 0 {
+                return Some(word_id * WORD_BITS + word.trailing_zeros() as u64);
+            }
+        }
+        None
+    }
+
+    // Returns the first set bit after the index.
+    pub fn next_set_after(&self, idx: u64) -> Option<u64> {
+        self.next_set_from(idx.checked_add(1)?)
+    }
+
+    // Iterates indices of set bits in increasing order.
+    pub fn iter_ones(&self) -> impl Iterator<Item = u64> + '_ {
+        std::iter::successors(self.next_set_from(0), |&idx| self.next_set_after(idx))
+    }
+
+    // Keeps only bits which are set in both bitmaps.
+    pub fn and_with(&mut self, other: &Self) {
+        for word_id in self.words.all_ids() {
+            self.words.set(word_id, &(self.word(word_id) & other.word(word_id)));
+        }
+    }
+
+    // Sets bits which are set in the other bitmap.
+    pub fn or_with(&mut self, other: &Self) -> Result<(), SetError> {
+        self.reserve_words(other.words.len())?;
+        for word_id in other.words.all_ids() {
+            self.words.set(word_id, &(self.word(word_id) | other.word(word_id)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+use std::{fs::File, io};
+use binbuf::{Entry, Fixed as _};
+use super::OpenMode;
+
+// Number of data bytes per chunk, the chunk with id of the next one takes 4096 bytes.
+pub const CHUNK_LEN: usize = 4088;
+
+binbuf::fixed! {
+    pub struct Chunk {
+        #[lens(buf_next_id)]
+        next_id: u64, // id + 1, 0 if there is none
+        #[lens(buf_data)]
+        data: [u8; CHUNK_LEN],
+    }
+    buf! { pub struct ChunkBuf<P>(Chunk, P); }
+
+    impl I for Chunk {
+        type Buf<P> = ChunkBuf<P>;
+    }
+    impl Code for Chunk {}
+}
+
+binbuf::fixed! {
+    pub struct Blob {
+        #[lens(buf_first_chunk_id)]
+        first_chunk_id: u64, // id + 1, 0 if there is none
+        #[lens(buf_last_chunk_id)]
+        last_chunk_id: u64, // id + 1, 0 if there is none
+        #[lens(buf_len)]
+        len: u64,
+    }
+    buf! { pub struct BlobBuf<P>(Blob, P); }
+
+    impl I for Blob {
+        type Buf<P> = BlobBuf<P>;
+    }
+    impl Code for Blob {}
+}
+
+#[derive(Debug)]
+pub enum AddChunkError {
+    AddChunk(super::fixed::AddError),
+    RemoveLastFreeId(super::fixed::RemoveLastError),
+}
+
+#[derive(Debug)]
+pub enum FreeChunkError {
+    RemoveIfLast(super::fixed::RemoveLastError),
+    AddFreeId(super::fixed::AddError),
+}
+
+#[derive(Debug)]
+pub enum AddError {
+    AddBlob(super::fixed::AddError),
+    RemoveLastFreeId(super::fixed::RemoveLastError),
+}
+
+#[derive(Debug)]
+pub enum TruncateError {
+    FreeChunk(FreeChunkError),
+}
+
+#[derive(Debug)]
+pub enum RemoveError {
+    FreeChunk(FreeChunkError),
+    RemoveIfLast(super::fixed::RemoveLastError),
+    AddFreeId(super::fixed::AddError),
+}
+
+#[derive(Debug)]
+pub enum OpenError {
+    FixedOpen(super::fixed::OpenError),
+}
+
+pub struct OpenFiles {
+    pub blobs: File,
+    pub blob_free_ids: File,
+    pub chunks: File,
+    pub chunk_free_ids: File,
+}
+
+pub struct OpenMaxMargins {
+    pub blobs: u64,
+    pub blob_free_ids: u64,
+    pub chunks: u64,
+    pub chunk_free_ids: u64,
+}
+
+pub struct OpenConfig {
+    pub mode: OpenMode,
+    pub files: OpenFiles,
+    pub max_margins: OpenMaxMargins,
+}
+
+// Appends bytes to the end of a blob.
+pub struct Writer<'a> {
+    storage: &'a mut Value,
+    blob_id: u64,
+}
+
+impl<'a> io::Write for Writer<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut blob = self.storage.blob(self.blob_id);
+        let offset = (blob.len % CHUNK_LEN as u64) as usize;
+        // Last chunk is full, or there are no chunks yet.
+        if offset == 0 {
+            let chunk_id = self.storage.add_chunk().map_err(|error| io::Error::other(format!("{error:?}")))?;
+            match blob.last_chunk_id.checked_sub(1) {
+                Some(last_chunk_id) => self.storage.set_next_chunk_id(last_chunk_id, Some(chunk_id)),
+                None => blob.first_chunk_id = chunk_id + 1,
+            }
+            blob.last_chunk_id = chunk_id + 1;
+        }
+
+        let len = buf.len().min(CHUNK_LEN - offset);
+        let data = unsafe { self.storage.data_mut(blob.last_chunk_id - 1) };
+        data[offset .. offset + len].copy_from_slice(&buf[.. len]);
+        blob.len += len as u64;
+        self.storage.set_blob(self.blob_id, &blob);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Reads bytes of a blob from any position.
+pub struct Reader<'a> {
+    storage: &'a Value,
+    blob_id: u64,
+    pos: u64,
+    chunk: Option<(u64, u64)>, // id of the last read chunk and position of its start
+}
+
+impl<'a> Reader<'a> {
+    // Returns id of the chunk holding the position and position of its start.
+    // Chunks are walked from the last read one if the position is after it.
+    fn find_chunk(&self, pos: u64) -> Option<(u64, u64)> {
+        let (mut chunk_id, mut start) = match self.chunk {
+            Some((chunk_id, start)) if start <= pos => (chunk_id, start),
+            _ => (self.storage.blob(self.blob_id).first_chunk_id.checked_sub(1)?, 0),
+        };
+        while pos >= start + CHUNK_LEN as u64 {
+            chunk_id = self.storage.next_chunk_id(chunk_id)?;
+            start += CHUNK_LEN as u64;
+        }
+        Some((chunk_id, start))
+    }
+}
+
+impl<'a> io::Read for Reader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let blob_len = self.storage.len(self.blob_id);
+        if self.pos >= blob_len || buf.is_empty() {
+            return Ok(0);
+        }
+        let Some((chunk_id, start)) = self.find_chunk(self.pos) else {
+            return Ok(0);
+        };
+        let offset = (self.pos - start) as usize;
+        let len = buf.len().min(CHUNK_LEN - offset).min((blob_len - self.pos) as usize);
+        buf[.. len].copy_from_slice(&unsafe { self.storage.data(chunk_id) }[offset .. offset + len]);
+        self.chunk = Some((chunk_id, start));
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<'a> io::Seek for Reader<'a> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            io::SeekFrom::Start(pos) => Some(pos),
+            io::SeekFrom::End(offset) => self.storage.len(self.blob_id).checked_add_signed(offset),
+            io::SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            },
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek to a negative or overflowing position")),
+        }
+    }
+}
+
+// Storage of many large byte values (blobs), each stored as a chain of fixed size chunks.
+// Chunks of removed or truncated blobs are reused.
+// Blob ids aren't checked, they must be returned by `add` and not removed.
+pub struct Value {
+    blobs: super::Fixed<Blob>,
+    blob_free_ids: super::Fixed<u64>,
+    chunks: super::Fixed<Chunk>,
+    chunk_free_ids: super::Fixed<u64>,
+}
+
+impl Value {
+    pub unsafe fn open(OpenConfig { mode, files, max_margins }: OpenConfig) -> Result<Self, OpenError> {
+        Ok(Self {
+            blobs: super::Fixed::open(mode, files.blobs, max_margins.blobs).map_err(OpenError::FixedOpen)?,
+            blob_free_ids: super::Fixed::open(mode, files.blob_free_ids, max_margins.blob_free_ids).map_err(OpenError::FixedOpen)?,
+            chunks: super::Fixed::open(mode, files.chunks, max_margins.chunks).map_err(OpenError::FixedOpen)?,
+            chunk_free_ids: super::Fixed::open(mode, files.chunk_free_ids, max_margins.chunk_free_ids).map_err(OpenError::FixedOpen)?,
+        })
+    }
+
+    fn blob(&self, blob_id: u64) -> Blob {
+        binbuf::fixed::decode::<Blob, _>(unsafe { self.blobs.buf_unchecked(blob_id) })
+    }
+
+    fn set_blob(&mut self, blob_id: u64, blob: &Blob) {
+        blob.encode(unsafe { self.blobs.buf_mut_unchecked(blob_id) });
+    }
+
+    // Length of the blob in bytes.
+    pub fn len(&self, blob_id: u64) -> u64 {
+        self.blob(blob_id).len
+    }
+
+    pub fn is_empty(&self, blob_id: u64) -> bool {
+        self.len(blob_id) == 0
+    }
+
+    fn next_chunk_id(&self, chunk_id: u64) -> Option<u64> {
+        binbuf::fixed::decode::<u64, _>(Chunk::buf_next_id(unsafe { self.chunks.buf_unchecked(chunk_id) })).checked_sub(1)
+    }
+
+    fn set_next_chunk_id(&mut self, chunk_id: u64, next_id: Option<u64>) {
+        next_id.map_or(0, |id| id + 1).encode(Chunk::buf_next_id(unsafe { self.chunks.buf_mut_unchecked(chunk_id) }));
+    }
+
+    // Data bytes of the chunk, they are borrowed from the storage.
+    // Doesn't check if the id is valid.
+    unsafe fn data(&self, chunk_id: u64) -> &[u8] {
+        <[u8; CHUNK_LEN]>::buf_ptr(Chunk::buf_data(self.chunks.buf_unchecked(chunk_id))).slice()
+    }
+
+    unsafe fn data_mut(&mut self, chunk_id: u64) -> &mut [u8] {
+        <[u8; CHUNK_LEN]>::buf_ptr(Chunk::buf_data(self.chunks.buf_mut_unchecked(chunk_id))).slice()
+    }
+
+    fn chunk_ids(&self, blob_id: u64) -> impl Iterator<Item = u64> + '_ {
+        std::iter::successors(self.blob(blob_id).first_chunk_id.checked_sub(1), |&id| self.next_chunk_id(id))
+    }
+
+    fn add_chunk(&mut self) -> Result<u64, AddChunkError> {
+        match self.chunk_free_ids.last_buf() {
+            Some(id_buf) => {
+                let id = binbuf::fixed::decode::<u64, _>(id_buf);
+                self.set_next_chunk_id(id, None);
+                self.chunk_free_ids.remove_last().map_err(AddChunkError::RemoveLastFreeId)?;
+                Ok(id)
+            },
+            None => self.chunks.add(&Chunk { next_id: 0, data: [0; CHUNK_LEN] }).map_err(AddChunkError::AddChunk),
+        }
+    }
+
+    fn free_chunk(&mut self, chunk_id: u64) -> Result<(), FreeChunkError> {
+        if self.chunks.remove_if_last(chunk_id).map_err(FreeChunkError::RemoveIfLast)? {
+            self.chunk_free_ids.add(&chunk_id).map_err(FreeChunkError::AddFreeId)?;
+        }
+        Ok(())
+    }
+
+    // Returns id of the new empty blob.
+    pub fn add(&mut self) -> Result<u64, AddError> {
+        let blob = Blob { first_chunk_id: 0, last_chunk_id: 0, len: 0 };
+        match self.blob_free_ids.last_buf() {
+            Some(id_buf) => {
+                let id = binbuf::fixed::decode::<u64, _>(id_buf);
+                self.set_blob(id, &blob);
+                self.blob_free_ids.remove_last().map_err(AddError::RemoveLastFreeId)?;
+                Ok(id)
+            },
+            None => self.blobs.add(&blob).map_err(AddError::AddBlob),
+        }
+    }
+
+    pub fn writer(&mut self, blob_id: u64) -> Writer<'_> {
+        Writer { storage: self, blob_id }
+    }
+
+    pub fn reader(&self, blob_id: u64) -> Reader<'_> {
+        Reader { storage: self, blob_id, pos: 0, chunk: None }
+    }
+
+    // Does nothing if the blob isn't longer than the length.
+    pub fn truncate(&mut self, blob_id: u64, len: u64) -> Result<(), TruncateError> {
+        let mut blob = self.blob(blob_id);
+        if blob.len <= len {
+            return Ok(());
+        }
+        let chunks_len = len.div_ceil(CHUNK_LEN as u64) as usize;
+        let chunk_ids = self.chunk_ids(blob_id).collect::<Vec<_>>();
+        for &chunk_id in &chunk_ids[chunks_len ..] {
+            self.free_chunk(chunk_id).map_err(TruncateError::FreeChunk)?;
+        }
+        match chunks_len.checked_sub(1).map(|idx| chunk_ids[idx]) {
+            Some(last_chunk_id) => {
+                self.set_next_chunk_id(last_chunk_id, None);
+                blob.last_chunk_id = last_chunk_id + 1;
+            },
+            None => {
+                blob.first_chunk_id = 0;
+                blob.last_chunk_id = 0;
+            },
+        }
+        blob.len = len;
+        self.set_blob(blob_id, &blob);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, blob_id: u64) -> Result<(), RemoveError> {
+        for chunk_id in self.chunk_ids(blob_id).collect::<Vec<_>>() {
+            self.free_chunk(chunk_id).map_err(RemoveError::FreeChunk)?;
+        }
+        if self.blobs.remove_if_last(blob_id).map_err(RemoveError::RemoveIfLast)? {
+            self.blob_free_ids.add(&blob_id).map_err(RemoveError::AddFreeId)?;
+        }
+        Ok(())
+    }
+
+    // Reads the whole blob.
+    pub fn get(&self, blob_id: u64) -> Vec<u8> {
+        let len = self.len(blob_id) as usize;
+        let mut bytes = Vec::with_capacity(len);
+        for chunk_id in self.chunk_ids(blob_id) {
+            let chunk_len = CHUNK_LEN.min(len - bytes.len());
+            bytes.extend_from_slice(&unsafe { self.data(chunk_id) }[.. chunk_len]);
+        }
+        bytes
+    }
+}
@@ -0,0 +1,161 @@
+use std::{fs::File, marker::PhantomData};
+use binbuf::{fixed::Readable, impls::{ordering::{Descending, Natural}, ArbNum, Compared}};
+use crate::utils::hash_bytes;
+use super::OpenMode;
+
+binbuf::fixed! {
+    pub struct Header {
+        #[lens(buf_bits_len)]
+        bits_len: u64,
+        #[lens(buf_hashes_len)]
+        hashes_len: u32,
+    }
+    buf! { pub struct HeaderBuf<P>(Header, P); }
+
+    impl I for Header {
+        type Buf<P> = HeaderBuf<P>;
+    }
+    impl Code for Header {}
+}
+
+// Keys which are equal only if their encoded bytes are equal, so keys found by comparisons can be filtered by hashes of their bytes.
+// Keys with a custom order, like `Compared<_, AsciiCaseInsensitive>`, mustn't implement it, the filter would miss their equal keys.
+pub trait BytesEq: binbuf::Fixed {}
+
+macro_rules! impl_bytes_eq {
+    ($($ty: ty),*) => {
+        $(impl BytesEq for $ty {})*
+    };
+}
+
+impl_bytes_eq!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, bool, char);
+
+impl<T: BytesEq, const N: usize> BytesEq for [T; N] {}
+
+impl<const LEN: usize> BytesEq for ArbNum<LEN, u64> {}
+
+impl<T: BytesEq + binbuf::fixed::Decode + Ord> BytesEq for Compared<T, Natural> {}
+
+impl<T: binbuf::Fixed, C> BytesEq for Compared<T, Descending<C>> where Compared<T, C>: BytesEq {}
+
+#[derive(Debug)]
+pub enum AddError {
+    SetBit(super::bitmap::SetError),
+}
+
+#[derive(Debug)]
+pub enum OpenError {
+    BitmapOpen(super::bitmap::OpenError),
+    SingleOpen(super::single::OpenError),
+    InvalidFalsePositiveRate, // must be between 0 and 1, exclusive
+}
+
+pub struct OpenFiles {
+    pub bits: File,
+    pub header: File,
+}
+
+pub struct OpenMaxMargins {
+    pub bits: u64,
+}
+
+pub struct OpenConfig {
+    pub mode: OpenMode,
+    pub files: OpenFiles,
+    pub max_margins: OpenMaxMargins,
+    // Size of a new filter is chosen from these, existing one keeps its own.
+    pub expected_len: u64,
+    pub false_positive_rate: f64,
+}
+
+// Set of keys which can tell for sure that a key was never added, but may be wrong about a key being added.
+// Keys are hashed by their encoded bytes, every key sets a few bits of the bitmap.
+pub struct Value<K> {
+    bits: super::Bitmap,
+    bits_len: u64,
+    hashes_len: u32,
+    _header: super::Single<Header>,
+    _marker: PhantomData<fn() -> K>,
+}
+
+impl<K: binbuf::Fixed> Value<K> {
+    pub unsafe fn open(OpenConfig { mode, files, max_margins, expected_len, false_positive_rate }: OpenConfig) -> Result<Self, OpenError> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(OpenError::InvalidFalsePositiveRate);
+        }
+        // Optimal number of bits and hashes for the expected number of keys.
+        let ln_2 = std::f64::consts::LN_2;
+        let expected_len = expected_len.max(1) as f64;
+        let bits_len = (-expected_len * false_positive_rate.ln() / (ln_2 * ln_2)).ceil().max(1.0) as u64;
+        let hashes_len = (bits_len as f64 / expected_len * ln_2).round().max(1.0) as u32;
+
+        let new_header = Header { bits_len, hashes_len };
+        let header = super::Single::open(
+            match mode {
+                OpenMode::New => super::single::OpenMode::New(&new_header),
+                OpenMode::Existing => super::single::OpenMode::Existing,
+            },
+            files.header,
+        )
+            .map_err(OpenError::SingleOpen)?;
+        let Header { bits_len, hashes_len } = header.get();
+
+        Ok(Self {
+            bits: super::Bitmap::open(mode, files.bits, max_margins.bits).map_err(OpenError::BitmapOpen)?,
+            bits_len,
+            hashes_len,
+            _header: header,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn bits_len(&self) -> u64 {
+        self.bits_len
+    }
+
+    pub fn hashes_len(&self) -> u32 {
+        self.hashes_len
+    }
+
+    // Bits of the key, made from two hashes (double hashing).
+    fn bit_ids(&self, key_bytes: &[u8]) -> impl Iterator<Item = u64> {
+        let hash = hash_bytes(key_bytes);
+        let step = hash_bytes(&hash.to_le_bytes()) | 1;
+        let bits_len = self.bits_len;
+        (0 .. self.hashes_len as u64).map(move |idx| hash.wrapping_add(idx.wrapping_mul(step)) % bits_len)
+    }
+
+    pub(super) fn add_bytes(&mut self, key_bytes: &[u8]) -> Result<(), AddError> {
+        for bit_id in self.bit_ids(key_bytes) {
+            self.bits.set(bit_id).map_err(AddError::SetBit)?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn may_contain_bytes(&self, key_bytes: &[u8]) -> bool {
+        self.bit_ids(key_bytes).all(|bit_id| self.bits.get(bit_id))
+    }
+
+    fn key_bytes(key: impl Readable<K>) -> Vec<u8> {
+        let mut bytes = vec![0; K::LEN];
+        unsafe { binbuf::fixed::encode_slice::<K>(&mut bytes, key) };
+        bytes
+    }
+
+    pub fn add(&mut self, key: impl Readable<K>) -> Result<(), AddError> {
+        self.add_bytes(&Self::key_bytes(key))
+    }
+
+    pub fn add_buf(&mut self, key: binbuf::BufConst<K>) -> Result<(), AddError> {
+        self.add_bytes(K::buf_ptr(key).slice())
+    }
+
+    // Returns false if the key was never added.
+    pub fn may_contain(&self, key: impl Readable<K>) -> bool {
+        self.may_contain_bytes(&Self::key_bytes(key))
+    }
+
+    pub fn may_contain_buf(&self, key: binbuf::BufConst<K>) -> bool {
+        self.may_contain_bytes(K::buf_ptr(key).slice())
+    }
+}
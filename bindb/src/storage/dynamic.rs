@@ -33,6 +33,7 @@ impl<P: BytesPtr> FreeLocationBuf<P> {
 pub enum AddError {
     Io(std::io::Error),
     FixedSwapRemove(super::fixed::SwapRemoveError),
+    AddBoundary(super::bitmap::SetError),
 }
 
 #[derive(Debug)]
@@ -42,20 +43,37 @@ pub enum RemoveError {
     FixedAdd(super::fixed::AddError),
 }
 
+#[derive(Debug)]
+pub enum ReplaceError {
+    Add(AddError),
+    Remove(RemoveError),
+}
+
 #[derive(Debug)]
 pub enum OpenError {
     Io(std::io::Error),
-    FixedOpen(super::fixed::OpenError)
+    FixedOpen(super::fixed::OpenError),
+    RebuildBoundaries(super::bitmap::SetError),
+}
+
+#[derive(Debug)]
+pub enum GetError {
+    InvalidId,
+    BoundariesNotTracked,
 }
 
 pub struct OpenFiles {
     pub entries: File,
     pub free_locations: File,
+    // Entry boundaries are only tracked if the file is given, ids can't be validated otherwise.
+    // Storages built on top of Dynamic don't track them, as they only use ids they got from it themselves.
+    pub boundaries: Option<File>,
 }
 
 pub struct OpenMaxMargins {
     pub entries: u64,
     pub free_locations: u64,
+    pub boundaries: u64,
 }
 
 
@@ -65,10 +83,38 @@ pub struct OpenConfig {
     pub max_margins: OpenMaxMargins,
 }
 
+pub struct Iter<'a, E> {
+    storage: &'a Value<E>,
+    free_locations: std::iter::Peekable<std::vec::IntoIter<FreeLocation>>,
+    offset: u64,
+}
+
+impl<'a, E: binbuf::Dynamic> Iterator for Iter<'a, E> {
+    type Item = (EntryId, binbuf::BufConst<E>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let offset = self.offset;
+            match self.free_locations.next_if(|loc| loc.start == offset) {
+                Some(loc) => self.offset = loc.end,
+                None => break,
+            }
+        }
+        if self.offset >= self.storage.bytes_len {
+            return None;
+        }
+        let id = EntryId(self.offset);
+        let buf = unsafe { self.storage.buf_unchecked(id) };
+        self.offset += binbuf::dynamic::buf_len::<E>(buf) as u64;
+        Some((id, buf))
+    }
+}
+
 pub struct Value<E> {
     len: u64,
     bytes_len: u64,
     free_locations: super::Fixed<FreeLocation>,
+    boundaries: Option<super::Bitmap>, // bit per byte of entries, set if an entry starts there
     entries_file: File,
     entries_mmap: MmapMut,
     margin: u64,
@@ -94,16 +140,40 @@ impl<E: binbuf::Dynamic> Value<E> {
                 Header { len: 0, bytes_len: 0 }
             }
         };
-        Ok(Self {
+        let mut value = Self {
             len: header.len,
             bytes_len: header.bytes_len,
             free_locations: super::Fixed::open(mode, files.free_locations, max_margins.free_locations).map_err(OpenError::FixedOpen)?,
+            boundaries: None,
             entries_file: files.entries,
             entries_mmap,
             margin: 0,
             max_margin: max_margins.entries,
             _marker: PhantomData
-        })
+        };
+        // Files written before entry count was stored have zero in its place, so it's counted once and persisted.
+        // Zero count with taken bytes is otherwise impossible, as removing all entries frees all bytes.
+        if value.len == 0 && value.bytes_len > 0 {
+            let len = value.iter().count() as u64;
+            value.set_len(len);
+        }
+        if let Some(file) = files.boundaries {
+            let is_file_empty = file.metadata().map_err(OpenError::Io)?.len() == 0;
+            let boundaries_mode = if is_file_empty { OpenMode::New } else { mode };
+            let mut boundaries = super::Bitmap::open(boundaries_mode, file, max_margins.boundaries).map_err(OpenError::FixedOpen)?;
+            // Boundaries are empty if entries were stored before the file was given, and stale if entries were
+            // changed while it wasn't given, so they are checked against the entries and rebuilt if they differ.
+            if !boundaries.iter_ones().eq(value.iter().map(|(id, _)| id.0)) {
+                for idx in boundaries.iter_ones().collect::<Vec<_>>() {
+                    boundaries.clear(idx);
+                }
+                for (id, _) in value.iter() {
+                    boundaries.set(id.0).map_err(OpenError::RebuildBoundaries)?;
+                }
+            }
+            value.boundaries = Some(boundaries);
+        }
+        Ok(value)
     }
 
     fn entry_offset(&self, id: EntryId) -> usize {
@@ -120,12 +190,39 @@ impl<E: binbuf::Dynamic> Value<E> {
         unsafe { Header::buf(ptr) }
     }
 
+    fn set_len(&mut self, value: u64) {
+        self.len = value;
+        value.encode(Header::buf_len(self.header_buf_mut()));
+    }
+
     fn set_bytes_len(&mut self, value: u64) {
         self.bytes_len = value;
         value.encode(Header::buf_bytes_len(self.header_buf_mut()));
     }
 
-    // Doesn't check if id is valid. It's impossible to check that.
+    fn set_boundary(&mut self, id: EntryId) -> Result<(), super::bitmap::SetError> {
+        match &mut self.boundaries {
+            Some(boundaries) => boundaries.set(id.0),
+            None => Ok(()),
+        }
+    }
+
+    fn clear_boundary(&mut self, id: EntryId) {
+        if let Some(boundaries) = &mut self.boundaries {
+            boundaries.clear(id.0);
+        }
+    }
+
+    // Checks if id points at the start of a live entry, which is only known if boundaries are tracked.
+    pub fn check_id(&self, id: EntryId) -> Result<(), GetError> {
+        match &self.boundaries {
+            Some(boundaries) if id.0 < self.bytes_len && boundaries.get(id.0) => Ok(()),
+            Some(_) => Err(GetError::InvalidId),
+            None => Err(GetError::BoundariesNotTracked),
+        }
+    }
+
+    // Doesn't check if id is valid, use `check_id` for that.
     // Id may be pointing to garbage.
     pub unsafe fn buf_unchecked(&self, id: EntryId) -> binbuf::BufConst<E> {
         let ptr = bytes_ptr::Const::from_slice(
@@ -142,6 +239,16 @@ impl<E: binbuf::Dynamic> Value<E> {
         E::buf(ptr)
     }
 
+    pub fn buf(&self, id: EntryId) -> Result<binbuf::BufConst<E>, GetError> {
+        self.check_id(id)?;
+        Ok(unsafe { self.buf_unchecked(id) })
+    }
+
+    pub fn buf_mut(&mut self, id: EntryId) -> Result<binbuf::BufMut<E>, GetError> {
+        self.check_id(id)?;
+        Ok(unsafe { self.buf_mut_unchecked(id) })
+    }
+
     pub fn add(&mut self, entry: impl binbuf::dynamic::Readable<E>) -> Result<EntryId, AddError> {
         let entry_len = entry.len();
         let entry_len_u64 = entry_len as u64;
@@ -153,6 +260,7 @@ impl<E: binbuf::Dynamic> Value<E> {
                 let buf = unsafe { self.buf_mut_unchecked(entry_id) };
                 let written_len = entry.write_to(buf);
                 debug_assert_eq!(written_len, entry_len);
+                self.set_boundary(entry_id).map_err(AddError::AddBoundary)?;
                 let left_len = loc_len - entry_len_u64;
                 if left_len == 0 {
                     unsafe { self.free_locations.swap_remove(loc_id).map_err(AddError::FixedSwapRemove) }?;
@@ -162,6 +270,7 @@ impl<E: binbuf::Dynamic> Value<E> {
                         end: loc.end,
                     });
                 }
+                self.set_len(self.len + 1);
                 return Ok(entry_id);
             }
         }
@@ -177,7 +286,9 @@ impl<E: binbuf::Dynamic> Value<E> {
             let entry_id = EntryId(self.bytes_len);
             let written_len = entry.write_to(unsafe { self.buf_mut_unchecked(entry_id) });
             debug_assert_eq!(written_len, entry_len);
+            self.set_boundary(entry_id).map_err(AddError::AddBoundary)?;
             self.set_bytes_len(self.bytes_len + entry_len_u64);
+            self.set_len(self.len + 1);
             self.margin = margin_extra - entry_len_u64;
             Ok(entry_id)
 
@@ -185,7 +296,9 @@ impl<E: binbuf::Dynamic> Value<E> {
             let entry_id = EntryId(self.bytes_len);
             let written_len = entry.write_to(unsafe { self.buf_mut_unchecked(entry_id) });
             debug_assert_eq!(written_len, entry_len);
+            self.set_boundary(entry_id).map_err(AddError::AddBoundary)?;
             self.set_bytes_len(self.bytes_len + entry_len_u64);
+            self.set_len(self.len + 1);
             self.margin -= entry_len_u64;
             Ok(entry_id)
         }
@@ -195,11 +308,40 @@ impl<E: binbuf::Dynamic> Value<E> {
         self.free_locations.len()
     }
 
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Walks entries in order of their location, skipping free locations.
+    pub fn iter(&self) -> Iter<'_, E> {
+        let mut free_locations = self.free_locations.all_ids()
+            .map(|loc_id| binbuf::fixed::decode::<FreeLocation, _>(unsafe { self.free_locations.buf_unchecked(loc_id) }))
+            .collect::<Vec<_>>();
+        free_locations.sort_unstable_by_key(|loc| loc.start);
+        Iter {
+            storage: self,
+            free_locations: free_locations.into_iter().peekable(),
+            offset: 0,
+        }
+    }
+
     pub unsafe fn remove(&mut self, id: EntryId) -> Result<(), RemoveError> {
         let entry_len = binbuf::dynamic::buf_len::<E>(self.buf_unchecked(id));
-        let entry_len_u64 = entry_len as u64;
+        self.free_location(id.0, id.0 + entry_len as u64)?;
+        self.clear_boundary(id);
+        self.set_len(self.len - 1);
+        Ok(())
+    }
+
+    // Marks bytes from start to end as free, merging them with adjacent free locations.
+    // Doesn't check if the range is actually taken.
+    unsafe fn free_location(&mut self, start: u64, end: u64) -> Result<(), RemoveError> {
         let mut entry_loc_store = pin!([0; FreeLocation::LEN]);
-        let entry_loc = FreeLocation { start: id.0, end: id.0 + entry_len_u64 };
+        let entry_loc = FreeLocation { start, end };
 
         let entry_loc_buf = binbuf::entry::buf_mut_from_slice::<FreeLocation>(&mut *entry_loc_store);
         entry_loc.encode(entry_loc_buf);
@@ -257,11 +399,31 @@ impl<E: binbuf::Dynamic> Value<E> {
         drop(entry_loc_store);
         Ok(())
     }
+
+    // Overwrites entry in place if the new value isn't longer than the old one, freeing the leftover tail.
+    // Otherwise the value is added to a new location and the old entry is removed.
+    // Returns id of the entry, which only changes if the entry was moved.
+    // Doesn't check if id is valid.
+    pub unsafe fn replace(&mut self, id: EntryId, value: impl binbuf::dynamic::Readable<E>) -> Result<EntryId, ReplaceError> {
+        let entry_len = binbuf::dynamic::buf_len::<E>(self.buf_unchecked(id)) as u64;
+        let value_len = value.len() as u64;
+        if value_len <= entry_len {
+            let written_len = value.write_to(self.buf_mut_unchecked(id));
+            debug_assert_eq!(written_len as u64, value_len);
+            if value_len < entry_len {
+                self.free_location(id.0 + value_len, id.0 + entry_len).map_err(ReplaceError::Remove)?;
+            }
+            Ok(id)
+        } else {
+            let new_id = self.add(value).map_err(ReplaceError::Add)?;
+            self.remove(id).map_err(ReplaceError::Remove)?;
+            Ok(new_id)
+        }
+    }
 }
 
 impl<E: binbuf::dynamic::Decode> Value<E> {
-    // Make sure ID is valid!
-    pub unsafe fn get(&self, id: EntryId) -> E {
-        E::decode(self.buf_unchecked(id)).0
+    pub fn get(&self, id: EntryId) -> Result<E, GetError> {
+        self.buf(id).map(|buf| E::decode(buf).0)
     }
 }
\ No newline at end of file
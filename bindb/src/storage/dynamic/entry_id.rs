@@ -1,6 +1,6 @@
 
 binbuf::fixed! {
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, Debug)]
     pub struct Value(pub u64);
 
     buf! { pub struct Buf<P>(Value, P); }
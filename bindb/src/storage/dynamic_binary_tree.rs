@@ -0,0 +1,349 @@
+use std::{fmt::Debug, fs::File, marker::PhantomData};
+use binbuf::Fixed as _;
+use super::{binary_tree::{Node, NodeId, SearchedFound}, dynamic::EntryId, OpenMode};
+
+#[derive(Debug)]
+pub enum AddError {
+    AddKey(super::dynamic::AddError),
+    AddValue(super::dynamic::AddError),
+    AddNode(super::binary_tree::AddError),
+    // Adding failed, and already added key or value couldn't be removed.
+    RemoveKey(super::dynamic::RemoveError),
+    RemoveValue(super::dynamic::RemoveError),
+}
+
+#[derive(Debug)]
+pub enum RemoveError {
+    RemoveNode(super::binary_tree::RemoveError),
+    RemoveKey(super::dynamic::RemoveError),
+    RemoveValue(super::dynamic::RemoveError),
+}
+
+#[derive(Debug)]
+pub enum SetError {
+    ReplaceValue(super::dynamic::ReplaceError),
+}
+
+#[derive(Debug)]
+pub enum OpenError {
+    BinaryTreeOpen(super::binary_tree::OpenError),
+    DynamicOpen(super::dynamic::OpenError),
+}
+
+pub struct SeparateFiles {
+    pub entries: File,
+    pub free_locations: File,
+}
+
+pub struct SeparateMaxMargins {
+    pub entries: u64,
+    pub free_locations: u64,
+}
+
+// Value written into a node by move.
+pub struct Owned<T>(T);
+
+impl<T: binbuf::Fixed> binbuf::fixed::Readable<T> for Owned<T> {
+    fn write_to(self, buf: binbuf::BufMut<T>) {
+        self.0.encode(buf);
+    }
+}
+
+// Fixed size value which is written into a node through its dynamic encoding.
+pub struct Written<R>(R);
+
+impl<T: binbuf::Fixed, R: binbuf::dynamic::Readable<T>> binbuf::fixed::Readable<T> for Written<R> {
+    fn write_to(self, buf: binbuf::BufMut<T>) {
+        self.0.write_to(buf);
+    }
+}
+
+// How keys or values of the tree are stored.
+pub trait Storing {
+    type Entry: binbuf::Dynamic;
+    // What nodes hold.
+    type Stored: binbuf::Fixed;
+    type Storage;
+    type Files;
+    type MaxMargins;
+    // What is needed to remove the entry from the storage.
+    type Id: Copy;
+    // What is written into the new node.
+    type Added<R: binbuf::dynamic::Readable<Self::Entry>>: binbuf::fixed::Readable<Self::Stored>;
+
+    unsafe fn open(mode: OpenMode, files: Self::Files, max_margins: Self::MaxMargins) -> Result<Self::Storage, super::dynamic::OpenError>;
+    unsafe fn buf(storage: &Self::Storage, stored: binbuf::BufConst<Self::Stored>) -> binbuf::BufConst<Self::Entry>;
+    unsafe fn buf_mut(storage: &mut Self::Storage, stored: binbuf::BufMut<Self::Stored>) -> binbuf::BufMut<Self::Entry>;
+    unsafe fn id(stored: binbuf::BufConst<Self::Stored>) -> Self::Id;
+    fn add<R: binbuf::dynamic::Readable<Self::Entry>>(storage: &mut Self::Storage, entry: R) -> Result<(Self::Id, Self::Added<R>), super::dynamic::AddError>;
+    unsafe fn remove(storage: &mut Self::Storage, id: Self::Id) -> Result<(), super::dynamic::RemoveError>;
+    // Stored value is updated if the entry is moved.
+    unsafe fn replace(
+        storage: &mut Self::Storage,
+        stored: binbuf::BufMut<Self::Stored>,
+        entry: impl binbuf::dynamic::Readable<Self::Entry>
+    ) -> Result<(), super::dynamic::ReplaceError>;
+}
+
+// Fixed size keys or values stored right in nodes.
+pub struct Inline<T>(PhantomData<T>);
+
+impl<T: binbuf::Fixed> Storing for Inline<T> {
+    type Entry = T;
+    type Stored = T;
+    type Storage = ();
+    type Files = ();
+    type MaxMargins = ();
+    type Id = ();
+    type Added<R: binbuf::dynamic::Readable<T>> = Written<R>;
+
+    unsafe fn open(_mode: OpenMode, _files: (), _max_margins: ()) -> Result<(), super::dynamic::OpenError> {
+        Ok(())
+    }
+
+    unsafe fn buf(_storage: &(), stored: binbuf::BufConst<T>) -> binbuf::BufConst<T> {
+        stored
+    }
+
+    unsafe fn buf_mut(_storage: &mut (), stored: binbuf::BufMut<T>) -> binbuf::BufMut<T> {
+        stored
+    }
+
+    unsafe fn id(_stored: binbuf::BufConst<T>) {}
+
+    fn add<R: binbuf::dynamic::Readable<T>>(_storage: &mut (), entry: R) -> Result<((), Written<R>), super::dynamic::AddError> {
+        Ok(((), Written(entry)))
+    }
+
+    unsafe fn remove(_storage: &mut (), _id: ()) -> Result<(), super::dynamic::RemoveError> {
+        Ok(())
+    }
+
+    unsafe fn replace(
+        _storage: &mut (),
+        stored: binbuf::BufMut<T>,
+        entry: impl binbuf::dynamic::Readable<T>
+    ) -> Result<(), super::dynamic::ReplaceError> {
+        entry.write_to(stored);
+        Ok(())
+    }
+}
+
+// Keys or values of a dynamic size stored in their own dynamic storage, nodes only hold their ids.
+pub struct Separate<T>(PhantomData<T>);
+
+impl<T: binbuf::Dynamic> Storing for Separate<T> {
+    type Entry = T;
+    type Stored = EntryId;
+    type Storage = super::Dynamic<T>;
+    type Files = SeparateFiles;
+    type MaxMargins = SeparateMaxMargins;
+    type Id = EntryId;
+    type Added<R: binbuf::dynamic::Readable<T>> = Owned<EntryId>;
+
+    unsafe fn open(mode: OpenMode, files: SeparateFiles, max_margins: SeparateMaxMargins) -> Result<super::Dynamic<T>, super::dynamic::OpenError> {
+        super::Dynamic::open(super::dynamic::OpenConfig {
+            mode,
+            files: super::dynamic::OpenFiles {
+                entries: files.entries,
+                free_locations: files.free_locations,
+                boundaries: None,
+            },
+            max_margins: super::dynamic::OpenMaxMargins {
+                entries: max_margins.entries,
+                free_locations: max_margins.free_locations,
+                boundaries: 0,
+            },
+        })
+    }
+
+    unsafe fn buf(storage: &super::Dynamic<T>, stored: binbuf::BufConst<EntryId>) -> binbuf::BufConst<T> {
+        storage.buf_unchecked(Self::id(stored))
+    }
+
+    unsafe fn buf_mut(storage: &mut super::Dynamic<T>, stored: binbuf::BufMut<EntryId>) -> binbuf::BufMut<T> {
+        storage.buf_mut_unchecked(Self::id(binbuf::buf_to_const::<EntryId, _>(stored)))
+    }
+
+    unsafe fn id(stored: binbuf::BufConst<EntryId>) -> EntryId {
+        binbuf::fixed::decode::<EntryId, _>(stored)
+    }
+
+    fn add<R: binbuf::dynamic::Readable<T>>(storage: &mut super::Dynamic<T>, entry: R) -> Result<(EntryId, Owned<EntryId>), super::dynamic::AddError> {
+        let id = storage.add(entry)?;
+        Ok((id, Owned(id)))
+    }
+
+    unsafe fn remove(storage: &mut super::Dynamic<T>, id: EntryId) -> Result<(), super::dynamic::RemoveError> {
+        storage.remove(id)
+    }
+
+    unsafe fn replace(
+        storage: &mut super::Dynamic<T>,
+        stored: binbuf::BufMut<EntryId>,
+        entry: impl binbuf::dynamic::Readable<T>
+    ) -> Result<(), super::dynamic::ReplaceError> {
+        let id = Self::id(binbuf::buf_to_const::<EntryId, _>(stored));
+        storage.replace(id, entry)?.encode(stored);
+        Ok(())
+    }
+}
+
+pub struct OpenFiles<K: Storing, V: Storing> {
+    pub nodes: File,
+    pub free_ids: File,
+    pub header: File,
+    pub keys: K::Files,
+    pub values: V::Files,
+}
+
+pub struct OpenMaxMargins<K: Storing, V: Storing> {
+    pub nodes: u64,
+    pub free_ids: u64,
+    pub keys: K::MaxMargins,
+    pub values: V::MaxMargins,
+}
+
+pub struct OpenConfig<K: Storing, V: Storing> {
+    pub mode: OpenMode,
+    pub files: OpenFiles<K, V>,
+    pub max_margins: OpenMaxMargins<K, V>,
+}
+
+// Binary tree map with keys and/or values of a dynamic size, like `Value<u64, Separate<String>, Inline<u64>>`.
+// Nodes hold `Inline` keys and values themselves, and only ids of `Separate` ones, which are stored in their own dynamic storages.
+pub struct Value<I: NodeId, K: Storing, V: Storing> {
+    tree: super::BinaryTree<I, K::Stored, V::Stored>,
+    keys: K::Storage,
+    values: V::Storage,
+}
+
+impl<I: NodeId, K: Storing, V: Storing> Value<I, K, V>
+where K::Stored: binbuf::fixed::Decode + Debug {
+    pub unsafe fn open(OpenConfig { mode, files, max_margins }: OpenConfig<K, V>) -> Result<Self, OpenError> {
+        Ok(Self {
+            tree: super::BinaryTree::open(super::binary_tree::OpenConfig {
+                mode,
+                files: super::binary_tree::OpenFiles {
+                    nodes: files.nodes,
+                    free_ids: files.free_ids,
+                    header: files.header,
+                },
+                max_margins: super::binary_tree::OpenMaxMargins {
+                    nodes: max_margins.nodes,
+                    free_ids: max_margins.free_ids,
+                },
+            }).map_err(OpenError::BinaryTreeOpen)?,
+            keys: K::open(mode, files.keys, max_margins.keys).map_err(OpenError::DynamicOpen)?,
+            values: V::open(mode, files.values, max_margins.values).map_err(OpenError::DynamicOpen)?,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    // Keys are compared through their buffers, so they don't have to be decoded.
+    pub fn search(&self, key: impl binbuf::dynamic::BufOrd<K::Entry> + Clone) -> super::binary_tree::Searched {
+        self.tree.search_by(|key_stored, _| key.clone().buf_cmp(unsafe { K::buf(&self.keys, key_stored) }))
+    }
+
+    pub unsafe fn buf_searched(&self, searched: &SearchedFound) -> binbuf::BufConst<V::Entry> {
+        V::buf(&self.values, self.tree.buf_searched(searched))
+    }
+
+    // Length of the value mustn't be changed through the buffer.
+    pub unsafe fn buf_mut_searched(&mut self, searched: &SearchedFound) -> binbuf::BufMut<V::Entry> {
+        V::buf_mut(&mut self.values, self.tree.buf_mut_searched(searched))
+    }
+
+    pub fn buf(&self, key: impl binbuf::dynamic::BufOrd<K::Entry> + Clone) -> Option<binbuf::BufConst<V::Entry>> {
+        self.search(key).find().ok().map(|s| unsafe { self.buf_searched(&s) })
+    }
+
+    pub fn buf_mut(&mut self, key: impl binbuf::dynamic::BufOrd<K::Entry> + Clone) -> Option<binbuf::BufMut<V::Entry>> {
+        self.search(key).find().ok().map(|s| unsafe { self.buf_mut_searched(&s) })
+    }
+
+    pub fn contains_key(&self, key: impl binbuf::dynamic::BufOrd<K::Entry> + Clone) -> bool {
+        self.search(key).is_found()
+    }
+
+    // Returns true if item already exists.
+    // If adding fails, already added key and value are removed, so nothing is left behind.
+    pub fn add(
+        &mut self,
+        key: impl binbuf::dynamic::BufOrd<K::Entry> + Clone,
+        value: impl binbuf::dynamic::Readable<V::Entry>
+    ) -> Result<bool, AddError>
+    where [(); Node::<I, K::Stored, V::Stored, ()>::LEN]: {
+        let searched = match self.search(key.clone()).find() {
+            Ok(_) => return Ok(true),
+            Err(s) => s,
+        };
+        let (key_id, key_stored) = K::add(&mut self.keys, key).map_err(AddError::AddKey)?;
+        let (value_id, value_stored) = match V::add(&mut self.values, value) {
+            Ok(added) => added,
+            Err(error) => {
+                unsafe { K::remove(&mut self.keys, key_id) }.map_err(AddError::RemoveKey)?;
+                return Err(AddError::AddValue(error));
+            },
+        };
+        if let Err(error) = unsafe { self.tree.add_searched(&searched, key_stored, value_stored) } {
+            unsafe { K::remove(&mut self.keys, key_id) }.map_err(AddError::RemoveKey)?;
+            unsafe { V::remove(&mut self.values, value_id) }.map_err(AddError::RemoveValue)?;
+            return Err(AddError::AddNode(error));
+        }
+        Ok(false)
+    }
+
+    // Separate value is moved if it doesn't fit into its old location.
+    // Returns true if item doesn't exist.
+    pub fn set(&mut self, key: impl binbuf::dynamic::BufOrd<K::Entry> + Clone, value: impl binbuf::dynamic::Readable<V::Entry>) -> Result<bool, SetError> {
+        let searched = match self.search(key).find() {
+            Ok(s) => s,
+            Err(_) => return Ok(true),
+        };
+        unsafe { V::replace(&mut self.values, self.tree.buf_mut_searched(&searched), value) }.map_err(SetError::ReplaceValue)?;
+        Ok(false)
+    }
+
+    pub unsafe fn remove_searched(&mut self, searched: &SearchedFound) -> Result<(), RemoveError> {
+        let key_id = K::id(self.tree.key_buf_searched(searched));
+        let value_id = V::id(self.tree.buf_searched(searched));
+        self.tree.remove_searched(searched).map_err(RemoveError::RemoveNode)?;
+        K::remove(&mut self.keys, key_id).map_err(RemoveError::RemoveKey)?;
+        V::remove(&mut self.values, value_id).map_err(RemoveError::RemoveValue)?;
+        Ok(())
+    }
+
+    // Returns true if item doesn't exist.
+    pub fn remove(&mut self, key: impl binbuf::dynamic::BufOrd<K::Entry> + Clone) -> Result<bool, RemoveError> {
+        match self.search(key).find() {
+            Ok(s) => {
+                unsafe { self.remove_searched(&s) }?;
+                Ok(false)
+            },
+            Err(_) => Ok(true)
+        }
+    }
+
+    // Iterates items in order of keys.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (binbuf::BufConst<K::Entry>, binbuf::BufConst<V::Entry>)> + '_ {
+        self.tree.iter().map(|(key_stored, value_stored)| unsafe {(
+            K::buf(&self.keys, key_stored),
+            V::buf(&self.values, value_stored),
+        )})
+    }
+}
+
+impl<I: NodeId, K: Storing, V: Storing> Value<I, K, V>
+where K::Stored: binbuf::fixed::Decode + Debug, V::Entry: binbuf::dynamic::Decode {
+    pub fn get(&self, key: impl binbuf::dynamic::BufOrd<K::Entry> + Clone) -> Option<V::Entry> {
+        self.buf(key).map(|buf| binbuf::dynamic::decode(buf).0)
+    }
+}
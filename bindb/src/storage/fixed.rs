@@ -17,6 +17,11 @@ pub enum AddError {
     Io(std::io::Error),
 }
 
+#[derive(Debug)]
+pub enum ReserveError {
+    Io(std::io::Error),
+}
+
 #[derive(Debug)]
 pub enum RemoveLastError {
     Io(std::io::Error),
@@ -148,6 +153,18 @@ impl<E: binbuf::Fixed> Value<E> {
         E::buf(ptr)
     }
 
+    // Bytes of the entries in the range, they are stored next to each other.
+    // Doesn't check if IDs are valid.
+    pub unsafe fn range_ptr_unchecked(&self, start_id: u64, end_id: u64) -> bytes_ptr::Const {
+        let (start, end) = (self.entry_offset(start_id), self.entry_offset(end_id));
+        bytes_ptr::Const::new(self.file_map.get_unchecked(start .. end).as_ptr(), end - start)
+    }
+
+    pub unsafe fn range_ptr_mut_unchecked(&mut self, start_id: u64, end_id: u64) -> bytes_ptr::Mut {
+        let (start, end) = (self.entry_offset(start_id), self.entry_offset(end_id));
+        bytes_ptr::Mut::new(self.file_map.get_unchecked_mut(start .. end).as_mut_ptr(), end - start)
+    }
+
     pub fn last_buf(&self) -> Option<binbuf::BufConst<E>> {
         if self.is_empty() {
             None
@@ -191,6 +208,30 @@ impl<E: binbuf::Fixed> Value<E> {
         Ok(id)
     }
 
+    // Extends the file up front, so that additional entries can be added without remapping it.
+    // Max margin is reserved on top, same as in `add`, so that repeated small reserves don't remap every time.
+    pub fn reserve(&mut self, additional: u64) -> Result<(), ReserveError> {
+        if self.margin < additional {
+            let new_len = self.entry_offset(self.next_entry_id + additional + self.max_margin);
+            self.file.set_len(new_len as u64).map_err(ReserveError::Io)?;
+            self.file_map = unsafe { MmapOptions::new().len(new_len).map_mut(&self.file).map_err(ReserveError::Io)? };
+            self.margin = additional + self.max_margin;
+        }
+        Ok(())
+    }
+
+    // Adds entries with zeroed bytes, which are meant to be written later.
+    // Returns id of the first added entry.
+    pub fn add_zeroed(&mut self, len: u64) -> Result<u64, ReserveError> {
+        let id = self.next_entry_id;
+        self.reserve(len)?;
+        let (start, end) = (self.entry_offset(id), self.entry_offset(id + len));
+        self.file_map[start .. end].fill(0);
+        self.margin -= len;
+        self.set_next_entry_id(id + len);
+        Ok(id)
+    }
+
     pub fn remove_last(&mut self) -> Result<(), RemoveLastError> {
         let id = self.next_entry_id;
         if self.margin >= self.max_margin {
@@ -0,0 +1,385 @@
+use std::fs::File;
+use binbuf::{bytes_ptr, fixed::Readable, Entry, Fixed as _};
+use crate::utils::hash_bytes;
+use super::OpenMode;
+
+binbuf::fixed! {
+    pub struct Slot<K, V> {
+        #[lens(buf_is_used)]
+        is_used: bool,
+        #[lens(buf_key)]
+        key: K,
+        #[lens(buf_value)]
+        value: V,
+        #[lens(buf_next_id)]
+        next_id: u64, // id of the next overflow slot + 1, 0 if there is none
+    }
+    buf! { pub struct SlotBuf<P, K: binbuf::Fixed, V: binbuf::Fixed>(Slot<K, V>, P); }
+
+    impl<K: binbuf::Fixed, V: binbuf::Fixed> I for Slot<K, V> {
+        type Buf<P> = SlotBuf<P, K, V>;
+    }
+
+    impl<K: binbuf::Fixed, V: binbuf::Fixed> Encode for Slot<K, V> {}
+    impl<K: binbuf::fixed::Decode, V: binbuf::fixed::Decode> Decode for Slot<K, V> {}
+}
+
+binbuf::fixed! {
+    pub struct Header {
+        #[lens(buf_level)]
+        level: u32,
+        #[lens(buf_split_id)]
+        split_id: u64, // next bucket to be split
+        #[lens(buf_len)]
+        len: u64,
+    }
+    buf! { pub struct HeaderBuf<P>(Header, P); }
+
+    impl I for Header {
+        type Buf<P> = HeaderBuf<P>;
+    }
+    impl Code for Header {}
+}
+
+#[derive(Debug)]
+pub enum FreeSlotError {
+    RemoveIfLast(super::fixed::RemoveLastError),
+    AddFreeId(super::fixed::AddError),
+}
+
+#[derive(Debug)]
+pub enum AddError {
+    AddBucket(super::fixed::AddError),
+    AddOverflowSlot(super::fixed::AddError),
+    RemoveLastFreeId(super::fixed::RemoveLastError),
+    FreeSlot(FreeSlotError),
+    AddToBloom(super::bloom::AddError),
+}
+
+#[derive(Debug)]
+pub enum RemoveError {
+    FreeSlot(FreeSlotError),
+}
+
+#[derive(Debug)]
+pub enum OpenError {
+    FixedOpen(super::fixed::OpenError),
+    SingleOpen(super::single::OpenError),
+    AddBucket(super::fixed::AddError),
+}
+
+pub struct OpenFiles {
+    pub buckets: File,
+    pub overflow_slots: File,
+    pub free_ids: File,
+    pub header: File,
+}
+
+pub struct OpenMaxMargins {
+    pub buckets: u64,
+    pub overflow_slots: u64,
+    pub free_ids: u64,
+}
+
+pub struct OpenConfig {
+    pub mode: OpenMode,
+    pub files: OpenFiles,
+    pub max_margins: OpenMaxMargins,
+}
+
+#[derive(Clone, Copy)]
+enum SlotId {
+    Bucket(u64),
+    Overflow(u64),
+}
+
+// Hash map with linear hashing: buckets are split one by one as the map grows, so it's never rehashed as a whole.
+// Every bucket holds one slot, colliding items are chained in overflow slots.
+// Keys are hashed and compared by their encoded bytes.
+pub struct Value<K, V> {
+    buckets: super::Fixed<Slot<K, V>>,
+    overflow_slots: super::Fixed<Slot<K, V>>,
+    free_ids: super::Fixed<u64>,
+    header: super::Single<Header>,
+    level: u32,
+    split_id: u64,
+    len: u64,
+    bloom: Option<super::Bloom<K>>, // lets lookups of missing keys skip the buckets
+}
+
+impl<K: binbuf::Fixed, V: binbuf::Fixed> Value<K, V> {
+    pub unsafe fn open(OpenConfig { mode, files, max_margins }: OpenConfig) -> Result<Self, OpenError> {
+        let header = super::Single::open(
+            match mode {
+                OpenMode::New => super::single::OpenMode::New(&Header { level: 0, split_id: 0, len: 0 }),
+                OpenMode::Existing => super::single::OpenMode::Existing,
+            },
+            files.header,
+        )
+            .map_err(OpenError::SingleOpen)?;
+        let Header { level, split_id, len } = header.get();
+
+        let mut value = Self {
+            buckets: super::Fixed::open(mode, files.buckets, max_margins.buckets).map_err(OpenError::FixedOpen)?,
+            overflow_slots: super::Fixed::open(mode, files.overflow_slots, max_margins.overflow_slots).map_err(OpenError::FixedOpen)?,
+            free_ids: super::Fixed::open(mode, files.free_ids, max_margins.free_ids).map_err(OpenError::FixedOpen)?,
+            header,
+            level,
+            split_id,
+            len,
+            bloom: None,
+        };
+        if let OpenMode::New = mode {
+            value.add_bucket().map_err(OpenError::AddBucket)?;
+        }
+        Ok(value)
+    }
+
+    // Keys of the map are added to the filter, and every added key is added to it from now on.
+    pub fn attach_bloom(&mut self, mut bloom: super::Bloom<K>) -> Result<(), super::bloom::AddError> {
+        for (key_buf, _) in self.iter() {
+            bloom.add_buf(key_buf)?;
+        }
+        self.bloom = Some(bloom);
+        Ok(())
+    }
+
+    pub fn detach_bloom(&mut self) -> Option<super::Bloom<K>> {
+        self.bloom.take()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn set_len(&mut self, len: u64) {
+        len.encode(Header::buf_len(self.header.buf_mut()));
+        self.len = len;
+    }
+
+    fn slot_buf(&self, id: SlotId) -> binbuf::BufConst<Slot<K, V>> {
+        match id {
+            SlotId::Bucket(id) => unsafe { self.buckets.buf_unchecked(id) },
+            SlotId::Overflow(id) => unsafe { self.overflow_slots.buf_unchecked(id) },
+        }
+    }
+
+    fn slot_buf_mut(&mut self, id: SlotId) -> binbuf::BufMut<Slot<K, V>> {
+        match id {
+            SlotId::Bucket(id) => unsafe { self.buckets.buf_mut_unchecked(id) },
+            SlotId::Overflow(id) => unsafe { self.overflow_slots.buf_mut_unchecked(id) },
+        }
+    }
+
+    fn is_used(&self, id: SlotId) -> bool {
+        binbuf::fixed::decode::<bool, _>(Slot::<K, V>::buf_is_used(self.slot_buf(id)))
+    }
+
+    fn next_id(&self, id: SlotId) -> Option<u64> {
+        binbuf::fixed::decode::<u64, _>(Slot::<K, V>::buf_next_id(self.slot_buf(id))).checked_sub(1)
+    }
+
+    fn set_next_id(slot: binbuf::BufMut<Slot<K, V>>, next_id: Option<u64>) {
+        next_id.map_or(0, |id| id + 1).encode(Slot::<K, V>::buf_next_id(slot));
+    }
+
+    fn key_bytes(key: impl Readable<K>) -> Vec<u8> {
+        let mut bytes = vec![0; K::LEN];
+        unsafe { binbuf::fixed::encode_slice::<K>(&mut bytes, key) };
+        bytes
+    }
+
+    fn bucket_id(&self, hash: u64) -> u64 {
+        let id = hash % (1 << self.level);
+        // Buckets before the split one are already split, so one more bit of the hash is used for them.
+        if id < self.split_id {
+            hash % (1 << (self.level + 1))
+        } else {
+            id
+        }
+    }
+
+    fn slot_ids(&self, bucket_id: u64) -> impl Iterator<Item = SlotId> + '_ {
+        std::iter::successors(Some(SlotId::Bucket(bucket_id)), |&id| self.next_id(id).map(SlotId::Overflow))
+    }
+
+    // Returns id of the slot holding the key and id of the slot before it in the chain.
+    fn find(&self, key_bytes: &[u8]) -> Option<(SlotId, Option<SlotId>)> {
+        if self.bloom.as_ref().is_some_and(|bloom| !bloom.may_contain_bytes(key_bytes)) {
+            return None;
+        }
+        let mut prev_id = None;
+        for id in self.slot_ids(self.bucket_id(hash_bytes(key_bytes))) {
+            let key_buf = Slot::<K, V>::buf_key(self.slot_buf(id));
+            if self.is_used(id) && K::buf_ptr(key_buf).slice() == key_bytes {
+                return Some((id, prev_id));
+            }
+            prev_id = Some(id);
+        }
+        None
+    }
+
+    fn add_bucket(&mut self) -> Result<u64, super::fixed::AddError> {
+        let mut slot = vec![0u8; Slot::<K, V>::LEN];
+        self.buckets.add(unsafe { Slot::<K, V>::buf(bytes_ptr::Mut::from_slice(&mut slot)) })
+    }
+
+    fn add_overflow_slot(&mut self, slot: binbuf::BufMut<Slot<K, V>>) -> Result<u64, AddError> {
+        match self.free_ids.last_buf() {
+            Some(id_buf) => {
+                let id = binbuf::fixed::decode::<u64, _>(id_buf);
+                slot.write_to(unsafe { self.overflow_slots.buf_mut_unchecked(id) });
+                self.free_ids.remove_last().map_err(AddError::RemoveLastFreeId)?;
+                Ok(id)
+            },
+            None => self.overflow_slots.add(slot).map_err(AddError::AddOverflowSlot),
+        }
+    }
+
+    fn free_overflow_slot(&mut self, id: u64) -> Result<(), FreeSlotError> {
+        if self.overflow_slots.remove_if_last(id).map_err(FreeSlotError::RemoveIfLast)? {
+            false.encode(Slot::<K, V>::buf_is_used(self.slot_buf_mut(SlotId::Overflow(id))));
+            self.free_ids.add(&id).map_err(FreeSlotError::AddFreeId)?;
+        }
+        Ok(())
+    }
+
+    // Puts the slot into the bucket, or into a new overflow slot right after it if the bucket is used.
+    fn insert(&mut self, bucket_id: u64, slot: &mut [u8]) -> Result<(), AddError> {
+        let slot_buf = unsafe { Slot::<K, V>::buf(bytes_ptr::Mut::from_slice(slot)) };
+        let bucket_slot_id = SlotId::Bucket(bucket_id);
+        if self.is_used(bucket_slot_id) {
+            Self::set_next_id(slot_buf, self.next_id(bucket_slot_id));
+            let id = self.add_overflow_slot(slot_buf)?;
+            Self::set_next_id(self.slot_buf_mut(bucket_slot_id), Some(id));
+        } else {
+            Self::set_next_id(slot_buf, None);
+            slot_buf.write_to(self.slot_buf_mut(bucket_slot_id));
+        }
+        Ok(())
+    }
+
+    // Moves items of the next bucket to be split between it and a new bucket.
+    fn split(&mut self) -> Result<(), AddError> {
+        let bucket_id = self.split_id;
+        self.add_bucket().map_err(AddError::AddBucket)?;
+
+        let mut slots = Vec::new();
+        for id in self.slot_ids(bucket_id).collect::<Vec<_>>() {
+            if self.is_used(id) {
+                slots.push(Slot::<K, V>::buf_ptr(self.slot_buf(id)).slice().to_vec());
+            }
+            if let SlotId::Overflow(id) = id {
+                self.free_overflow_slot(id).map_err(AddError::FreeSlot)?;
+            }
+        }
+        let bucket_slot = self.slot_buf_mut(SlotId::Bucket(bucket_id));
+        false.encode(Slot::<K, V>::buf_is_used(bucket_slot));
+        Self::set_next_id(bucket_slot, None);
+
+        let (level, split_id) = if self.split_id + 1 == 1 << self.level {
+            (self.level + 1, 0)
+        } else {
+            (self.level, self.split_id + 1)
+        };
+        let header = self.header.buf_mut();
+        level.encode(Header::buf_level(header));
+        split_id.encode(Header::buf_split_id(header));
+        self.level = level;
+        self.split_id = split_id;
+
+        for mut slot in slots {
+            let slot_buf = unsafe { Slot::<K, V>::buf(bytes_ptr::Const::from_slice(&slot)) };
+            let bucket_id = self.bucket_id(hash_bytes(K::buf_ptr(Slot::<K, V>::buf_key(slot_buf)).slice()));
+            self.insert(bucket_id, &mut slot)?;
+        }
+        Ok(())
+    }
+
+    pub fn contains_key(&self, key: impl Readable<K>) -> bool {
+        self.find(&Self::key_bytes(key)).is_some()
+    }
+
+    pub fn buf(&self, key: impl Readable<K>) -> Option<binbuf::BufConst<V>> {
+        let (id, _) = self.find(&Self::key_bytes(key))?;
+        Some(Slot::<K, V>::buf_value(self.slot_buf(id)))
+    }
+
+    pub fn buf_mut(&mut self, key: impl Readable<K>) -> Option<binbuf::BufMut<V>> {
+        let (id, _) = self.find(&Self::key_bytes(key))?;
+        Some(Slot::<K, V>::buf_value(self.slot_buf_mut(id)))
+    }
+
+    // Returns true if item already exists.
+    pub fn add(&mut self, key: impl Readable<K>, value: impl Readable<V>) -> Result<bool, AddError> {
+        let key_bytes = Self::key_bytes(key);
+        if self.find(&key_bytes).is_some() {
+            return Ok(true);
+        }
+
+        // Key is added to the filter first, if the insert fails it only makes a false positive.
+        if let Some(bloom) = &mut self.bloom {
+            bloom.add_bytes(&key_bytes).map_err(AddError::AddToBloom)?;
+        }
+        let mut slot = vec![0u8; Slot::<K, V>::LEN];
+        let slot_buf = unsafe { Slot::<K, V>::buf(bytes_ptr::Mut::from_slice(&mut slot)) };
+        true.encode(Slot::<K, V>::buf_is_used(slot_buf));
+        K::buf_ptr(Slot::<K, V>::buf_key(slot_buf)).copy_from_slice(&key_bytes);
+        value.write_to(Slot::<K, V>::buf_value(slot_buf));
+        self.insert(self.bucket_id(hash_bytes(&key_bytes)), &mut slot)?;
+        self.set_len(self.len + 1);
+
+        // Buckets are split while there are more than 3 items per 4 buckets.
+        if self.len * 4 > self.buckets.len() * 3 {
+            self.split()?;
+        }
+        Ok(false)
+    }
+
+    // Returns true if item doesn't exist.
+    pub fn remove(&mut self, key: impl Readable<K>) -> Result<bool, RemoveError> {
+        let Some((id, prev_id)) = self.find(&Self::key_bytes(key)) else {
+            return Ok(true);
+        };
+        let next_id = self.next_id(id);
+        match (id, prev_id) {
+            // Bucket slot is replaced by the next slot of the chain, so that used buckets always come first.
+            (SlotId::Bucket(_), _) => match next_id {
+                Some(next_id) => {
+                    let next_slot = self.slot_buf(SlotId::Overflow(next_id));
+                    binbuf::fixed::buf_copy_to::<Slot<K, V>>(next_slot, self.slot_buf_mut(id));
+                    self.free_overflow_slot(next_id).map_err(RemoveError::FreeSlot)?;
+                },
+                None => false.encode(Slot::<K, V>::buf_is_used(self.slot_buf_mut(id))),
+            },
+            (SlotId::Overflow(overflow_id), Some(prev_id)) => {
+                Self::set_next_id(self.slot_buf_mut(prev_id), next_id);
+                self.free_overflow_slot(overflow_id).map_err(RemoveError::FreeSlot)?;
+            },
+            (SlotId::Overflow(_), None) => unreachable!("Overflow slot always follows another slot"),
+        }
+        self.set_len(self.len - 1);
+        Ok(false)
+    }
+
+    // Iterates items in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (binbuf::BufConst<K>, binbuf::BufConst<V>)> + '_ {
+        self.buckets.all_ids()
+            .flat_map(|bucket_id| self.slot_ids(bucket_id))
+            .filter(|&id| self.is_used(id))
+            .map(|id| {
+                let slot = self.slot_buf(id);
+                (Slot::<K, V>::buf_key(slot), Slot::<K, V>::buf_value(slot))
+            })
+    }
+}
+
+impl<K: binbuf::Fixed, V: binbuf::fixed::Decode> Value<K, V> {
+    pub fn get(&self, key: impl Readable<K>) -> Option<V> {
+        self.buf(key).map(|buf| binbuf::fixed::decode::<V, _>(buf))
+    }
+}
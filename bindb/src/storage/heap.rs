@@ -0,0 +1,131 @@
+use std::{cmp::Ordering, fs::File};
+use binbuf::fixed::{BufOrd, Readable};
+use super::OpenMode;
+
+pub use super::fixed::OpenError;
+
+#[derive(Debug)]
+pub enum PushError {
+    AddEntry(super::fixed::AddError),
+}
+
+#[derive(Debug)]
+pub enum RemoveTopError {
+    RemoveLast(super::fixed::RemoveLastError),
+}
+
+// Min-heap of fixed sized entries, the entry with the smallest key is on top.
+// Key is read from the entry buffer with a lens, so only keys are decoded while sifting.
+// Max-heap can be made with a reversed key, such as `binbuf::impls::Reverse`.
+pub struct Value<E: binbuf::Entry, K: binbuf::Entry = E> {
+    entries: super::Fixed<E>,
+    key: fn(binbuf::BufConst<E>) -> binbuf::BufConst<K>,
+}
+
+impl<E: binbuf::fixed::Decode> Value<E, E> where for<'a> &'a E: BufOrd<E> {
+    // Entries are ordered as a whole.
+    pub unsafe fn open(mode: OpenMode, file: File, max_margin: u64) -> Result<Self, OpenError> {
+        Self::open_by_key(mode, file, max_margin, |buf| buf)
+    }
+}
+
+impl<E: binbuf::Fixed, K: binbuf::fixed::Decode> Value<E, K> where for<'a> &'a K: BufOrd<K> {
+    // Entries are ordered by the key, which is usually a field lens like `Task::buf_deadline`.
+    pub unsafe fn open_by_key(
+        mode: OpenMode,
+        file: File,
+        max_margin: u64,
+        key: fn(binbuf::BufConst<E>) -> binbuf::BufConst<K>,
+    ) -> Result<Self, OpenError> {
+        Ok(Self {
+            entries: super::Fixed::open(mode, file, max_margin)?,
+            key,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Doesn't check if ids are valid.
+    unsafe fn cmp(&self, a_id: u64, b_id: u64) -> Ordering {
+        let a_key = binbuf::fixed::decode::<K, _>((self.key)(self.entries.buf_unchecked(a_id)));
+        (&a_key).buf_cmp((self.key)(self.entries.buf_unchecked(b_id)))
+    }
+
+    unsafe fn sift_up(&mut self, mut id: u64) {
+        while id > 0 {
+            let parent_id = (id - 1) / 2;
+            if self.cmp(id, parent_id) != Ordering::Less {
+                break;
+            }
+            self.entries.swap(id, parent_id);
+            id = parent_id;
+        }
+    }
+
+    unsafe fn sift_down(&mut self, mut id: u64) {
+        let len = self.len();
+        loop {
+            let mut min_id = id;
+            for child_id in [id * 2 + 1, id * 2 + 2] {
+                if child_id < len && self.cmp(child_id, min_id) == Ordering::Less {
+                    min_id = child_id;
+                }
+            }
+            if min_id == id {
+                break;
+            }
+            self.entries.swap(id, min_id);
+            id = min_id;
+        }
+    }
+
+    pub fn push(&mut self, entry: impl Readable<E>) -> Result<(), PushError> {
+        let id = self.entries.add(entry).map_err(PushError::AddEntry)?;
+        unsafe { self.sift_up(id) };
+        Ok(())
+    }
+
+    pub fn peek_buf(&self) -> Option<binbuf::BufConst<E>> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(unsafe { self.entries.buf_unchecked(0) })
+        }
+    }
+
+    // Returns true if the heap is empty.
+    pub fn remove_top(&mut self) -> Result<bool, RemoveTopError> {
+        let Some(last_id) = self.entries.last_entry_id() else {
+            return Ok(true);
+        };
+        unsafe { self.entries.swap(0, last_id) };
+        self.entries.remove_last().map_err(RemoveTopError::RemoveLast)?;
+        unsafe { self.sift_down(0) };
+        Ok(false)
+    }
+
+    // Iterates entries in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = binbuf::BufConst<E>> + '_ {
+        self.entries.all_ids().map(|id| unsafe { self.entries.buf_unchecked(id) })
+    }
+}
+
+impl<E: binbuf::fixed::Decode, K: binbuf::fixed::Decode> Value<E, K> where for<'a> &'a K: BufOrd<K> {
+    pub fn peek(&self) -> Option<E> {
+        self.peek_buf().map(|buf| binbuf::fixed::decode::<E, _>(buf))
+    }
+
+    pub fn pop(&mut self) -> Result<Option<E>, RemoveTopError> {
+        let Some(entry) = self.peek() else {
+            return Ok(None);
+        };
+        self.remove_top()?;
+        Ok(Some(entry))
+    }
+}
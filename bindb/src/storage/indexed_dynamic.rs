@@ -3,23 +3,66 @@ use super::OpenMode;
 
 pub use super::dynamic::EntryId as DynamicEntryId;
 
-type IndexData = DynamicEntryId;
+binbuf::fixed! {
+    // Index of the slot and its generation at the time the entry was added.
+    // Generation of the slot is incremented both when the entry is added and when it's removed,
+    // so it's odd while the slot is occupied, and an id becomes invalid even if the slot is reused.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Id {
+        #[lens(pub buf_index)]
+        pub index: u64,
+        #[lens(pub buf_generation)]
+        pub generation: u32,
+    }
+
+    buf! { pub struct IdBuf<P>(Id, P); }
+
+    impl I for Id {
+        type Buf<P> = IdBuf<P>;
+    }
+
+    impl Code for Id {}
+}
+
+binbuf::fixed! {
+    // Generation was added after raw id, so indices stored before that have a different layout
+    // and can't be opened.
+    #[derive(Clone, Copy)]
+    struct IndexData {
+        #[lens(buf_raw_id)]
+        raw_id: DynamicEntryId,
+        #[lens(buf_generation)]
+        generation: u32,
+    }
+
+    buf! { struct IndexDataBuf<P>(IndexData, P); }
+
+    impl I for IndexData {
+        type Buf<P> = IndexDataBuf<P>;
+    }
+
+    impl Code for IndexData {}
+}
 
 #[derive(Debug)]
 pub enum AddError {
     RawAdd(super::dynamic::AddError),
     AddIndex(super::fixed::AddError),
-    RemoveFreeId(super::fixed::RemoveLastError)
+    RemoveFreeId(super::fixed::RemoveLastError),
+    RemoveRaw(super::dynamic::RemoveError),
 }
 
 #[derive(Debug)]
 pub enum RemoveError {
     RawRemove(super::dynamic::RemoveError),
-    AddIndex(super::fixed::AddError),
-    RemoveLastIndex(super::fixed::RemoveLastError),
     AddFreeId(super::fixed::AddError),
 }
 
+#[derive(Debug)]
+pub enum SetError {
+    RawReplace(super::dynamic::ReplaceError),
+}
+
 #[derive(Debug)]
 pub enum OpenError {
     DynamicOpen(super::dynamic::OpenError),
@@ -57,16 +100,57 @@ impl<E: binbuf::Dynamic> Value<E> {
         Ok(Self {
             raw: super::Dynamic::open(super::dynamic::OpenConfig {
                 mode,
-                files: super::dynamic::OpenFiles { entries: files.raw_entries, free_locations: files.raw_free_locations },
-                max_margins: super::dynamic::OpenMaxMargins { entries: max_margins.raw_entries, free_locations: max_margins.raw_free_locations },
+                files: super::dynamic::OpenFiles {
+                    entries: files.raw_entries,
+                    free_locations: files.raw_free_locations,
+                    boundaries: None,
+                },
+                max_margins: super::dynamic::OpenMaxMargins {
+                    entries: max_margins.raw_entries,
+                    free_locations: max_margins.raw_free_locations,
+                    boundaries: 0,
+                },
             }).map_err(OpenError::DynamicOpen)?,
             indices: super::Fixed::open(mode, files.indices, max_margins.indices).map_err(OpenError::FixedOpen)?,
             free_ids: super::Fixed::open(mode, files.free_ids, max_margins.free_ids).map_err(OpenError::FixedOpen)?,
         })
     }
 
-    pub fn is_id_valid(&self, id: u64) -> bool {
-        id < self.indices.len()
+    // Doesn't check if index is valid.
+    unsafe fn raw_id(&self, index: u64) -> DynamicEntryId {
+        binbuf::fixed::decode::<DynamicEntryId, _>(IndexData::buf_raw_id(self.indices.buf_unchecked(index)))
+    }
+
+    // Doesn't check if index is valid.
+    unsafe fn generation(&self, index: u64) -> u32 {
+        binbuf::fixed::decode::<u32, _>(IndexData::buf_generation(self.indices.buf_unchecked(index)))
+    }
+
+    pub fn contains(&self, id: Id) -> bool {
+        id.generation % 2 == 1
+            && id.index < self.indices.len()
+            && unsafe { self.generation(id.index) } == id.generation
+    }
+
+    // Count of stored entries.
+    pub fn len(&self) -> u64 {
+        self.raw.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Ids of stored entries, freed slots are skipped.
+    pub fn ids(&self) -> impl Iterator<Item = Id> + '_ {
+        self.indices.all_ids().filter_map(|index| {
+            let generation = unsafe { self.generation(index) };
+            (generation % 2 == 1).then_some(Id { index, generation })
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Id, binbuf::BufConst<E>)> + '_ {
+        self.ids().map(|id| (id, unsafe { self.buf_unchecked(id) }))
     }
 
     // pub unsafe fn create(raw: super::Dynamic<E>, indices_file: File, free_ids_file: File) -> Result<Self, CreateError> {
@@ -77,62 +161,101 @@ impl<E: binbuf::Dynamic> Value<E> {
     //     })
     // }
 
-    pub unsafe fn buf_unchecked(&self, id: u64) -> binbuf::BufConst<E> {
-        let raw_id = binbuf::fixed::decode::<IndexData, _>(self.indices.buf_unchecked(id));
-        self.raw.buf_unchecked(raw_id)
+    // Doesn't check if id is valid, generation is ignored.
+    pub unsafe fn buf_unchecked(&self, id: Id) -> binbuf::BufConst<E> {
+        self.raw.buf_unchecked(self.raw_id(id.index))
     }
 
-    pub unsafe fn buf_mut_unchecked(&mut self, id: u64) -> binbuf::BufMut<E> {
-        let raw_id = binbuf::fixed::decode::<IndexData, _>(self.indices.buf_unchecked(id));
+    pub unsafe fn buf_mut_unchecked(&mut self, id: Id) -> binbuf::BufMut<E> {
+        let raw_id = self.raw_id(id.index);
         self.raw.buf_mut_unchecked(raw_id)
     }
 
-    pub fn buf(&self, id: u64) -> binbuf::BufConst<E> {
-        if self.is_id_valid(id) {
-            unsafe { self.buf_unchecked(id) }
+    pub fn buf(&self, id: Id) -> Option<binbuf::BufConst<E>> {
+        if self.contains(id) {
+            Some(unsafe { self.buf_unchecked(id) })
         } else {
-            panic!("Id is invalid: {id}");
+            None
         }
     }
 
-    pub fn buf_mut(&mut self, id: u64) -> binbuf::BufMut<E> {
-        if self.is_id_valid(id) {
-            unsafe { self.buf_mut_unchecked(id) }
+    pub fn buf_mut(&mut self, id: Id) -> Option<binbuf::BufMut<E>> {
+        if self.contains(id) {
+            Some(unsafe { self.buf_mut_unchecked(id) })
         } else {
-            panic!("Id is invalid: {id}");
+            None
         }
     }
 
-    pub fn add(&mut self, value: impl binbuf::dynamic::Readable<E>) -> Result<u64, AddError> {
+    pub fn add(&mut self, value: impl binbuf::dynamic::Readable<E>) -> Result<Id, AddError> {
         let raw_id = self.raw.add(value).map_err(AddError::RawAdd)?;
-        let id = if let Some(id_buf) = self.free_ids.last_buf() {
-            let id = binbuf::fixed::decode::<u64, _>(id_buf);
+        match self.add_index(raw_id) {
+            Ok(id) => Ok(id),
+            // Raw entry isn't referenced by any slot, so it's removed to not leak.
+            Err(error) => {
+                unsafe { self.raw.remove(raw_id) }.map_err(AddError::RemoveRaw)?;
+                Err(error)
+            }
+        }
+    }
+
+    fn add_index(&mut self, raw_id: DynamicEntryId) -> Result<Id, AddError> {
+        if let Some(index_buf) = self.free_ids.last_buf() {
+            let index = binbuf::fixed::decode::<u64, _>(index_buf);
             self.free_ids.remove_last().map_err(AddError::RemoveFreeId)?;
-            self.indices.set(id, &raw_id);
-            id
+            let generation = unsafe { self.generation(index) }.wrapping_add(1);
+            self.indices.set(index, &IndexData { raw_id, generation });
+            Ok(Id { index, generation })
         } else {
-            self.indices.add(&raw_id).map_err(AddError::AddIndex)?
-        };
-        Ok(id)
+            let index = self.indices.add(&IndexData { raw_id, generation: 1 }).map_err(AddError::AddIndex)?;
+            Ok(Id { index, generation: 1 })
+        }
     }
 
     pub fn free_locations_len(&self) -> u64 {
         self.raw.free_locations_len()
     }
 
-    pub unsafe fn remove(&mut self, id: u64) -> Result<(), RemoveError> {
-        let raw_id = binbuf::fixed::decode::<IndexData, _>(self.indices.buf_unchecked(id));
-        self.raw.remove(raw_id).map_err(RemoveError::RawRemove)?;
+    // Returns true if id doesn't exist.
+    pub fn remove(&mut self, id: Id) -> Result<bool, RemoveError> {
+        if !self.contains(id) {
+            return Ok(true);
+        }
+        let raw_id = unsafe { self.raw_id(id.index) };
+        unsafe { self.raw.remove(raw_id) }.map_err(RemoveError::RawRemove)?;
+
+        // Slot is never removed, so that its generation is kept.
+        self.indices.set(id.index, &IndexData { raw_id, generation: id.generation.wrapping_add(1) });
+        self.free_ids.add(&id.index).map_err(RemoveError::AddFreeId)?;
+        Ok(false)
+    }
 
-        if self.indices.remove_if_last(id).map_err(RemoveError::RemoveLastIndex)? {
-            self.free_ids.add(&id).map_err(RemoveError::AddFreeId)?;
+    // Id stays the same even if the entry has to be moved.
+    // Returns true if id doesn't exist.
+    pub fn set(&mut self, id: Id, value: impl binbuf::dynamic::Readable<E>) -> Result<bool, SetError> {
+        if !self.contains(id) {
+            return Ok(true);
         }
-        Ok(())
+        let raw_id = unsafe { self.raw_id(id.index) };
+        let new_raw_id = unsafe { self.raw.replace(raw_id, value) }.map_err(SetError::RawReplace)?;
+        if new_raw_id.0 != raw_id.0 {
+            self.indices.set(id.index, &IndexData { raw_id: new_raw_id, generation: id.generation });
+        }
+        Ok(false)
     }
 }
 
 impl<E: binbuf::dynamic::Decode> Value<E> {
-    pub fn get(&self, id: u64) -> E {
-        binbuf::dynamic::decode(self.buf(id)).0
+    pub fn get(&self, id: Id) -> Option<E> {
+        self.buf(id).map(|buf| binbuf::dynamic::decode(buf).0)
     }
-}
\ No newline at end of file
+
+    // Same as `set`, but returns the old value.
+    pub fn replace(&mut self, id: Id, value: impl binbuf::dynamic::Readable<E>) -> Result<Option<E>, SetError> {
+        let Some(old_value) = self.get(id) else {
+            return Ok(None);
+        };
+        self.set(id, value)?;
+        Ok(Some(old_value))
+    }
+}
@@ -0,0 +1,212 @@
+use std::{fs::File, marker::PhantomData};
+use binbuf::bytes_ptr;
+use crate::utils::hash_bytes;
+use super::{indexed_dynamic::Id, OpenMode};
+
+#[derive(Debug)]
+pub enum InternError {
+    AddValue(super::indexed_dynamic::AddError),
+    AddToIndex(super::binary_tree::AddError),
+    AddRefCounts(super::fixed::ReserveError),
+    RemoveValue(super::indexed_dynamic::RemoveError),
+}
+
+#[derive(Debug)]
+pub enum ReleaseError {
+    RemoveFromIndex(super::binary_tree::RemoveError),
+    RemoveValue(super::indexed_dynamic::RemoveError),
+}
+
+#[derive(Debug)]
+pub enum OpenError {
+    IndexedDynamicOpen(super::indexed_dynamic::OpenError),
+    BinaryTreeOpen(super::binary_tree::OpenError),
+    FixedOpen(super::fixed::OpenError),
+}
+
+pub struct OpenFiles {
+    pub value_entries: File,
+    pub value_free_locations: File,
+    pub value_indices: File,
+    pub value_free_ids: File,
+    pub index_nodes: File,
+    pub index_free_ids: File,
+    pub index_header: File,
+    pub ref_counts: File,
+}
+
+pub struct OpenMaxMargins {
+    pub value_entries: u64,
+    pub value_free_locations: u64,
+    pub value_indices: u64,
+    pub value_free_ids: u64,
+    pub index_nodes: u64,
+    pub index_free_ids: u64,
+    pub ref_counts: u64,
+}
+
+pub struct OpenConfig {
+    pub mode: OpenMode,
+    pub files: OpenFiles,
+    pub max_margins: OpenMaxMargins,
+}
+
+// Value which is already encoded.
+struct Encoded<'a, E>(&'a [u8], PhantomData<E>);
+
+impl<'a, E: binbuf::Dynamic> binbuf::dynamic::Readable<E> for Encoded<'a, E> {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn write_to(self, buf: binbuf::BufMut<E>) -> usize {
+        E::buf_ptr(buf).slice()[.. self.0.len()].copy_from_slice(self.0);
+        self.0.len()
+    }
+}
+
+fn encode<E: binbuf::Dynamic>(value: impl binbuf::dynamic::Readable<E>) -> Vec<u8> {
+    let mut bytes = vec![0; value.len()];
+    value.write_to(unsafe { E::buf(bytes_ptr::Mut::from_slice(&mut bytes)) });
+    bytes
+}
+
+// Stores every distinct value once, equal values get the same id.
+// Values are found by hash of their encoded bytes, and are removed once every interned copy is released.
+pub struct Value<E> {
+    values: super::IndexedDynamic<E>,
+    index: super::BinaryTreeMulti<u64, u64, Id>, // hash of the value to its id
+    ref_counts: super::Fixed<u64>, // by index of the id
+}
+
+impl<E: binbuf::Dynamic> Value<E> {
+    pub unsafe fn open(OpenConfig { mode, files, max_margins }: OpenConfig) -> Result<Self, OpenError> {
+        Ok(Self {
+            values: super::IndexedDynamic::open(super::indexed_dynamic::OpenConfig {
+                mode,
+                files: super::indexed_dynamic::OpenFiles {
+                    raw_entries: files.value_entries,
+                    raw_free_locations: files.value_free_locations,
+                    indices: files.value_indices,
+                    free_ids: files.value_free_ids,
+                },
+                max_margins: super::indexed_dynamic::OpenMaxMargins {
+                    raw_entries: max_margins.value_entries,
+                    raw_free_locations: max_margins.value_free_locations,
+                    indices: max_margins.value_indices,
+                    free_ids: max_margins.value_free_ids,
+                },
+            }).map_err(OpenError::IndexedDynamicOpen)?,
+            index: super::BinaryTreeMulti::open(super::binary_tree::OpenConfig {
+                mode,
+                files: super::binary_tree::OpenFiles {
+                    nodes: files.index_nodes,
+                    free_ids: files.index_free_ids,
+                    header: files.index_header,
+                },
+                max_margins: super::binary_tree::OpenMaxMargins {
+                    nodes: max_margins.index_nodes,
+                    free_ids: max_margins.index_free_ids,
+                },
+            }).map_err(OpenError::BinaryTreeOpen)?,
+            ref_counts: super::Fixed::open(mode, files.ref_counts, max_margins.ref_counts).map_err(OpenError::FixedOpen)?,
+        })
+    }
+
+    // Count of distinct values.
+    pub fn len(&self) -> u64 {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn value_bytes(&self, id: Id) -> &[u8] {
+        let buf = unsafe { self.values.buf_unchecked(id) };
+        &E::buf_ptr(buf).slice()[.. binbuf::dynamic::buf_len::<E>(buf)]
+    }
+
+    fn find_bytes(&self, hash: u64, bytes: &[u8]) -> Option<Id> {
+        self.index.get_all(&hash)
+            .map(|id_buf| binbuf::fixed::decode::<Id, _>(id_buf))
+            .find(|&id| self.value_bytes(id) == bytes)
+    }
+
+    // Returns id of the value if it's interned, without interning it.
+    pub fn find(&self, value: impl binbuf::dynamic::Readable<E>) -> Option<Id> {
+        let bytes = encode::<E>(value);
+        self.find_bytes(hash_bytes(&bytes), &bytes)
+    }
+
+    pub fn ref_count(&self, id: Id) -> Option<u64> {
+        if self.values.contains(id) {
+            Some(binbuf::fixed::decode::<u64, _>(unsafe { self.ref_counts.buf_unchecked(id.index) }))
+        } else {
+            None
+        }
+    }
+
+    // Returns id of the equal value if it's already stored, otherwise stores the value.
+    // Either way, reference count of the value is incremented.
+    pub fn intern(&mut self, value: impl binbuf::dynamic::Readable<E>) -> Result<Id, InternError> {
+        let bytes = encode::<E>(value);
+        let hash = hash_bytes(&bytes);
+        if let Some(id) = self.find_bytes(hash, &bytes) {
+            let ref_count = binbuf::fixed::decode::<u64, _>(unsafe { self.ref_counts.buf_unchecked(id.index) });
+            self.ref_counts.set(id.index, &(ref_count + 1));
+            return Ok(id);
+        }
+
+        let id = self.values.add(Encoded(&bytes, PhantomData)).map_err(InternError::AddValue)?;
+        // Value which can't be found by its hash would never be released, so it's removed.
+        if let Err(error) = self.add_to_index(hash, id) {
+            self.values.remove(id).map_err(InternError::RemoveValue)?;
+            return Err(error);
+        }
+        Ok(id)
+    }
+
+    // Index is changed last, so nothing has to be removed from it if adding ref counts fails.
+    fn add_to_index(&mut self, hash: u64, id: Id) -> Result<(), InternError> {
+        if self.ref_counts.len() <= id.index {
+            self.ref_counts.add_zeroed(id.index + 1 - self.ref_counts.len()).map_err(InternError::AddRefCounts)?;
+        }
+        self.index.insert(&hash, &id).map_err(InternError::AddToIndex)?;
+        self.ref_counts.set(id.index, &1u64);
+        Ok(())
+    }
+
+    // Decrements reference count of the value, and removes it once the count gets to zero.
+    // Returns true if id doesn't exist.
+    pub fn release(&mut self, id: Id) -> Result<bool, ReleaseError> {
+        let Some(ref_count) = self.ref_count(id) else {
+            return Ok(true);
+        };
+        if ref_count > 1 {
+            self.ref_counts.set(id.index, &(ref_count - 1));
+            return Ok(false);
+        }
+
+        let hash = hash_bytes(self.value_bytes(id));
+        self.index.remove(&hash, &id).map_err(ReleaseError::RemoveFromIndex)?;
+        self.values.remove(id).map_err(ReleaseError::RemoveValue)?;
+        self.ref_counts.set(id.index, &0u64);
+        Ok(false)
+    }
+
+    pub fn resolve_buf(&self, id: Id) -> Option<binbuf::BufConst<E>> {
+        self.values.buf(id)
+    }
+
+    // Iterates distinct values with their ids.
+    pub fn iter(&self) -> impl Iterator<Item = (Id, binbuf::BufConst<E>)> + '_ {
+        self.values.iter()
+    }
+}
+
+impl<E: binbuf::dynamic::Decode> Value<E> {
+    pub fn resolve(&self, id: Id) -> Option<E> {
+        self.values.get(id)
+    }
+}
@@ -0,0 +1,260 @@
+use std::{collections::BTreeSet, fs::File, marker::PhantomData, path::{Path, PathBuf}};
+use super::OpenMode;
+
+#[derive(Debug)]
+pub enum SegmentOpenError {
+    Io(std::io::Error),
+    FixedOpen(super::fixed::OpenError),
+}
+
+#[derive(Debug)]
+pub enum AppendError {
+    SegmentOpen(SegmentOpenError),
+    AddRecord(super::fixed::ReserveError),
+    AddIndex(super::fixed::AddError),
+}
+
+#[derive(Debug)]
+pub enum RemoveSegmentsError {
+    SetFirstOffset(super::single::SetError),
+    Io(std::io::Error),
+}
+
+#[derive(Debug)]
+pub enum OpenError {
+    Io(std::io::Error),
+    SegmentOpen(SegmentOpenError),
+    SingleOpen(super::single::OpenError),
+    NoSegments,
+}
+
+pub struct OpenMaxMargins {
+    // In bytes, the records file is extended by it on top of the appended record, so it should fit a few records.
+    pub records: u64,
+    pub index: u64,
+}
+
+pub struct OpenConfig {
+    pub mode: OpenMode,
+    pub dir: PathBuf,
+    // New segment is started once the last one takes this many bytes.
+    pub max_segment_len: u64,
+    pub max_margins: OpenMaxMargins,
+}
+
+// Missing files of an existing log aren't created, so they are reported instead of being read as empty.
+fn open_file(path: &Path, mode: OpenMode) -> Result<File, std::io::Error> {
+    File::options().read(true).write(true).create(matches!(mode, OpenMode::New)).open(path)
+}
+
+const SEGMENT_EXTENSIONS: [&str; 2] = ["records", "index"];
+
+// File with offset of the first record which isn't removed.
+const FIRST_OFFSET_FILE_NAME: &str = "first_offset";
+
+fn segment_path(dir: &Path, base_offset: u64, extension: &str) -> PathBuf {
+    dir.join(format!("{base_offset:020}.{extension}"))
+}
+
+// Removes files of the segment, files which are already removed are skipped.
+fn remove_segment_files(dir: &Path, base_offset: u64) -> Result<(), std::io::Error> {
+    for extension in SEGMENT_EXTENSIONS {
+        match std::fs::remove_file(segment_path(dir, base_offset, extension)) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => return Err(error),
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+// Records are encoded one after another, they are never moved or removed.
+struct Segment<E> {
+    base_offset: u64, // offset of the first record
+    records: super::Fixed<u8>,
+    index: super::Fixed<u64>, // position of the record in records by its offset
+    _marker: PhantomData<fn() -> E>,
+}
+
+impl<E: binbuf::Dynamic> Segment<E> {
+    unsafe fn open(mode: OpenMode, dir: &Path, base_offset: u64, max_margins: &OpenMaxMargins) -> Result<Self, SegmentOpenError> {
+        let file = |extension| open_file(&segment_path(dir, base_offset, extension), mode).map_err(SegmentOpenError::Io);
+        Ok(Self {
+            base_offset,
+            records: super::Fixed::open(mode, file("records")?, max_margins.records).map_err(SegmentOpenError::FixedOpen)?,
+            index: super::Fixed::open(mode, file("index")?, max_margins.index).map_err(SegmentOpenError::FixedOpen)?,
+            _marker: PhantomData,
+        })
+    }
+
+    fn next_offset(&self) -> u64 {
+        self.base_offset + self.index.len()
+    }
+
+    unsafe fn position(&self, offset: u64) -> u64 {
+        binbuf::fixed::decode::<u64, _>(self.index.buf_unchecked(offset - self.base_offset))
+    }
+
+    unsafe fn buf_unchecked(&self, offset: u64) -> binbuf::BufConst<E> {
+        let end = if offset + 1 < self.next_offset() { self.position(offset + 1) } else { self.records.len() };
+        E::buf(self.records.range_ptr_unchecked(self.position(offset), end))
+    }
+
+    fn append(&mut self, record: impl binbuf::dynamic::Readable<E>) -> Result<(), AppendError> {
+        let len = record.len() as u64;
+        let position = self.records.add_zeroed(len).map_err(AppendError::AddRecord)?;
+        record.write_to(unsafe { E::buf(self.records.range_ptr_mut_unchecked(position, position + len)) });
+        self.index.add(&position).map_err(AppendError::AddIndex)?;
+        Ok(())
+    }
+}
+
+// Append-only log of dynamic sized records, which are addressed by increasing offsets.
+// Records are split between segments stored in the directory, files of a segment are named by offset of its first record.
+// Records are never removed one by one, only whole segments are.
+// Offset of the first kept record is stored before segments are removed, so files of partially removed segments are removed on open.
+pub struct Value<E> {
+    dir: PathBuf,
+    first_offset: super::Single<u64>,
+    segments: Vec<Segment<E>>, // sorted by offsets, never empty
+    max_segment_len: u64,
+    max_margins: OpenMaxMargins,
+}
+
+impl<E: binbuf::Dynamic> Value<E> {
+    pub unsafe fn open(OpenConfig { mode, dir, max_segment_len, max_margins }: OpenConfig) -> Result<Self, OpenError> {
+        if let OpenMode::New = mode {
+            std::fs::create_dir_all(&dir).map_err(OpenError::Io)?;
+        }
+        let first_offset = super::Single::<u64>::open(
+            match mode {
+                OpenMode::New => super::single::OpenMode::New(&0),
+                OpenMode::Existing => super::single::OpenMode::Existing,
+            },
+            open_file(&dir.join(FIRST_OFFSET_FILE_NAME), mode).map_err(OpenError::Io)?,
+        )
+            .map_err(OpenError::SingleOpen)?;
+        let segments = match mode {
+            OpenMode::New => vec![Segment::open(mode, &dir, 0, &max_margins).map_err(OpenError::SegmentOpen)?],
+            OpenMode::Existing => {
+                let mut base_offsets = BTreeSet::new();
+                for entry in std::fs::read_dir(&dir).map_err(OpenError::Io)? {
+                    let path = entry.map_err(OpenError::Io)?.path();
+                    if path.extension().is_some_and(|extension| SEGMENT_EXTENSIONS.iter().any(|segment_extension| extension == *segment_extension)) {
+                        if let Some(base_offset) = path.file_stem().and_then(|stem| stem.to_str()?.parse::<u64>().ok()) {
+                            base_offsets.insert(base_offset);
+                        }
+                    }
+                }
+                // Segments before the first offset were being removed when the log was closed.
+                let first_offset = first_offset.get();
+                for &base_offset in base_offsets.range(.. first_offset) {
+                    remove_segment_files(&dir, base_offset).map_err(OpenError::Io)?;
+                }
+                base_offsets.range(first_offset ..)
+                    .map(|&base_offset| Segment::open(mode, &dir, base_offset, &max_margins))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(OpenError::SegmentOpen)?
+            },
+        };
+        if segments.is_empty() {
+            return Err(OpenError::NoSegments);
+        }
+        Ok(Self {
+            dir,
+            first_offset,
+            segments,
+            max_segment_len,
+            max_margins,
+        })
+    }
+
+    fn last_segment(&self) -> &Segment<E> {
+        self.segments.last().unwrap()
+    }
+
+    // Offset of the oldest record which isn't removed.
+    pub fn first_offset(&self) -> u64 {
+        self.segments[0].base_offset
+    }
+
+    // Offset which the next appended record will get.
+    pub fn next_offset(&self) -> u64 {
+        self.last_segment().next_offset()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.next_offset() - self.first_offset()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn segments_len(&self) -> usize {
+        self.segments.len()
+    }
+
+    // Index of the segment which holds (or would hold) the offset.
+    fn segment_idx(&self, offset: u64) -> usize {
+        self.segments.partition_point(|segment| segment.base_offset <= offset).saturating_sub(1)
+    }
+
+    pub fn buf(&self, offset: u64) -> Option<binbuf::BufConst<E>> {
+        if offset < self.first_offset() || offset >= self.next_offset() {
+            return None;
+        }
+        Some(unsafe { self.segments[self.segment_idx(offset)].buf_unchecked(offset) })
+    }
+
+    // Returns offset of the record.
+    pub fn append(&mut self, record: impl binbuf::dynamic::Readable<E>) -> Result<u64, AppendError> {
+        let last_segment = self.last_segment();
+        if last_segment.records.len() >= self.max_segment_len && !last_segment.index.is_empty() {
+            let segment = unsafe { Segment::open(OpenMode::New, &self.dir, self.next_offset(), &self.max_margins) }
+                .map_err(AppendError::SegmentOpen)?;
+            self.segments.push(segment);
+        }
+
+        let offset = self.next_offset();
+        self.segments.last_mut().unwrap().append(record)?;
+        Ok(offset)
+    }
+
+    // Iterates records with their offsets, starting from the offset.
+    pub fn iter_from(&self, offset: u64) -> impl Iterator<Item = (u64, binbuf::BufConst<E>)> + '_ {
+        let offset = offset.max(self.first_offset());
+        self.segments[self.segment_idx(offset) ..].iter().flat_map(move |segment| {
+            (offset.max(segment.base_offset) .. segment.next_offset())
+                .map(move |offset| (offset, unsafe { segment.buf_unchecked(offset) }))
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u64, binbuf::BufConst<E>)> + '_ {
+        self.iter_from(self.first_offset())
+    }
+
+    // Removes segments whose records all come before the offset, the last segment is always kept.
+    // If removing files fails, the rest of them are removed on the next open.
+    // Returns number of removed segments.
+    pub fn remove_segments_before(&mut self, offset: u64) -> Result<usize, RemoveSegmentsError> {
+        let count = self.segments.windows(2)
+            .take_while(|segments| segments[1].base_offset <= offset)
+            .count();
+        if count == 0 {
+            return Ok(0);
+        }
+        self.first_offset.set(&self.segments[count].base_offset).map_err(RemoveSegmentsError::SetFirstOffset)?;
+        // Segments are dropped first, so their files are unmapped before being removed.
+        let base_offsets = self.segments.drain(.. count).map(|segment| segment.base_offset).collect::<Vec<_>>();
+        for base_offset in base_offsets {
+            remove_segment_files(&self.dir, base_offset).map_err(RemoveSegmentsError::Io)?;
+        }
+        Ok(count)
+    }
+}
+
+impl<E: binbuf::dynamic::Decode> Value<E> {
+    pub fn get(&self, offset: u64) -> Option<E> {
+        self.buf(offset).map(|buf| binbuf::dynamic::decode(buf).0)
+    }
+}
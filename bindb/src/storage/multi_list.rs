@@ -0,0 +1,288 @@
+use std::fs::File;
+use binbuf::{fixed::Readable, Fixed as _};
+use super::OpenMode;
+
+// Number of items per block.
+pub const BLOCK_LEN: u64 = 8;
+
+binbuf::fixed! {
+    pub struct List {
+        #[lens(buf_first_block_id)]
+        first_block_id: u64, // id + 1, 0 if there is none
+        #[lens(buf_last_block_id)]
+        last_block_id: u64, // id + 1, 0 if there is none
+        #[lens(buf_len)]
+        len: u64,
+    }
+    buf! { pub struct ListBuf<P>(List, P); }
+
+    impl I for List {
+        type Buf<P> = ListBuf<P>;
+    }
+    impl Code for List {}
+}
+
+#[derive(Debug)]
+pub enum AddBlockError {
+    AddBlock(super::fixed::AddError),
+    AddItems(super::fixed::ReserveError),
+    RemoveLastFreeId(super::fixed::RemoveLastError),
+}
+
+#[derive(Debug)]
+pub enum FreeBlockError {
+    RemoveIfLast(super::fixed::RemoveLastError),
+    RemoveLastItem(super::fixed::RemoveLastError),
+    AddFreeId(super::fixed::AddError),
+}
+
+#[derive(Debug)]
+pub enum AddListError {
+    AddList(super::fixed::AddError),
+    RemoveLastFreeId(super::fixed::RemoveLastError),
+}
+
+#[derive(Debug)]
+pub enum RemoveListError {
+    FreeBlock(FreeBlockError),
+    RemoveIfLast(super::fixed::RemoveLastError),
+    AddFreeId(super::fixed::AddError),
+}
+
+#[derive(Debug)]
+pub enum PushError {
+    AddBlock(AddBlockError),
+}
+
+#[derive(Debug)]
+pub enum RemoveError {
+    FreeBlock(FreeBlockError),
+}
+
+#[derive(Debug)]
+pub enum OpenError {
+    FixedOpen(super::fixed::OpenError),
+}
+
+pub struct OpenFiles {
+    pub lists: File,
+    pub list_free_ids: File,
+    pub blocks: File,
+    pub block_free_ids: File,
+    pub items: File,
+}
+
+pub struct OpenMaxMargins {
+    pub lists: u64,
+    pub list_free_ids: u64,
+    pub blocks: u64,
+    pub block_free_ids: u64,
+    pub items: u64,
+}
+
+pub struct OpenConfig {
+    pub mode: OpenMode,
+    pub files: OpenFiles,
+    pub max_margins: OpenMaxMargins,
+}
+
+// Many independent lists of fixed sized items stored in shared files.
+// Every list is a chain of blocks of `BLOCK_LEN` items, all blocks except the last one are full.
+// Items of a block are stored next to each other, starting from id of the block * `BLOCK_LEN`.
+// List ids aren't checked, they must be returned by `add_list` and not removed.
+pub struct Value<E> {
+    lists: super::Fixed<List>,
+    list_free_ids: super::Fixed<u64>,
+    blocks: super::Fixed<u64>, // id of the next block + 1, 0 if there is none
+    block_free_ids: super::Fixed<u64>,
+    items: super::Fixed<E>,
+}
+
+impl<E: binbuf::Fixed> Value<E> {
+    pub unsafe fn open(OpenConfig { mode, files, max_margins }: OpenConfig) -> Result<Self, OpenError> {
+        Ok(Self {
+            lists: super::Fixed::open(mode, files.lists, max_margins.lists).map_err(OpenError::FixedOpen)?,
+            list_free_ids: super::Fixed::open(mode, files.list_free_ids, max_margins.list_free_ids).map_err(OpenError::FixedOpen)?,
+            blocks: super::Fixed::open(mode, files.blocks, max_margins.blocks).map_err(OpenError::FixedOpen)?,
+            block_free_ids: super::Fixed::open(mode, files.block_free_ids, max_margins.block_free_ids).map_err(OpenError::FixedOpen)?,
+            items: super::Fixed::open(mode, files.items, max_margins.items).map_err(OpenError::FixedOpen)?,
+        })
+    }
+
+    // Number of lists.
+    pub fn lists_len(&self) -> u64 {
+        self.lists.len() - self.list_free_ids.len()
+    }
+
+    fn list(&self, list_id: u64) -> List {
+        binbuf::fixed::decode::<List, _>(unsafe { self.lists.buf_unchecked(list_id) })
+    }
+
+    fn set_list(&mut self, list_id: u64, list: &List) {
+        list.encode(unsafe { self.lists.buf_mut_unchecked(list_id) });
+    }
+
+    pub fn len(&self, list_id: u64) -> u64 {
+        self.list(list_id).len
+    }
+
+    pub fn is_empty(&self, list_id: u64) -> bool {
+        self.len(list_id) == 0
+    }
+
+    fn next_block_id(&self, block_id: u64) -> Option<u64> {
+        binbuf::fixed::decode::<u64, _>(unsafe { self.blocks.buf_unchecked(block_id) }).checked_sub(1)
+    }
+
+    fn set_next_block_id(&mut self, block_id: u64, next_id: Option<u64>) {
+        next_id.map_or(0, |id| id + 1).encode(unsafe { self.blocks.buf_mut_unchecked(block_id) });
+    }
+
+    fn block_ids(&self, list_id: u64) -> impl Iterator<Item = u64> + '_ {
+        std::iter::successors(self.list(list_id).first_block_id.checked_sub(1), |&id| self.next_block_id(id))
+    }
+
+    // Ids of items of the list in their order.
+    fn item_ids(&self, list_id: u64) -> impl Iterator<Item = u64> + '_ {
+        self.block_ids(list_id)
+            .flat_map(|block_id| block_id * BLOCK_LEN .. (block_id + 1) * BLOCK_LEN)
+            .take(self.len(list_id) as usize)
+    }
+
+    // Returns id of the new list.
+    pub fn add_list(&mut self) -> Result<u64, AddListError> {
+        let list = List { first_block_id: 0, last_block_id: 0, len: 0 };
+        match self.list_free_ids.last_buf() {
+            Some(id_buf) => {
+                let id = binbuf::fixed::decode::<u64, _>(id_buf);
+                self.set_list(id, &list);
+                self.list_free_ids.remove_last().map_err(AddListError::RemoveLastFreeId)?;
+                Ok(id)
+            },
+            None => self.lists.add(&list).map_err(AddListError::AddList),
+        }
+    }
+
+    pub fn remove_list(&mut self, list_id: u64) -> Result<(), RemoveListError> {
+        for block_id in self.block_ids(list_id).collect::<Vec<_>>() {
+            self.free_block(block_id).map_err(RemoveListError::FreeBlock)?;
+        }
+        if self.lists.remove_if_last(list_id).map_err(RemoveListError::RemoveIfLast)? {
+            self.list_free_ids.add(&list_id).map_err(RemoveListError::AddFreeId)?;
+        }
+        Ok(())
+    }
+
+    fn add_block(&mut self) -> Result<u64, AddBlockError> {
+        match self.block_free_ids.last_buf() {
+            Some(id_buf) => {
+                let id = binbuf::fixed::decode::<u64, _>(id_buf);
+                self.set_next_block_id(id, None);
+                self.block_free_ids.remove_last().map_err(AddBlockError::RemoveLastFreeId)?;
+                Ok(id)
+            },
+            None => {
+                let id = self.blocks.add(&0u64).map_err(AddBlockError::AddBlock)?;
+                self.items.add_zeroed(BLOCK_LEN).map_err(AddBlockError::AddItems)?;
+                Ok(id)
+            },
+        }
+    }
+
+    fn free_block(&mut self, block_id: u64) -> Result<(), FreeBlockError> {
+        if self.blocks.remove_if_last(block_id).map_err(FreeBlockError::RemoveIfLast)? {
+            self.block_free_ids.add(&block_id).map_err(FreeBlockError::AddFreeId)?;
+        } else {
+            for _ in 0 .. BLOCK_LEN {
+                self.items.remove_last().map_err(FreeBlockError::RemoveLastItem)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn push(&mut self, list_id: u64, entry: impl Readable<E>) -> Result<(), PushError> {
+        let mut list = self.list(list_id);
+        if list.len % BLOCK_LEN == 0 {
+            let block_id = self.add_block().map_err(PushError::AddBlock)?;
+            match list.last_block_id.checked_sub(1) {
+                Some(last_block_id) => self.set_next_block_id(last_block_id, Some(block_id)),
+                None => list.first_block_id = block_id + 1,
+            }
+            list.last_block_id = block_id + 1;
+        }
+        let item_id = (list.last_block_id - 1) * BLOCK_LEN + list.len % BLOCK_LEN;
+        entry.write_to(unsafe { self.items.buf_mut_unchecked(item_id) });
+        list.len += 1;
+        self.set_list(list_id, &list);
+        Ok(())
+    }
+
+    pub fn buf(&self, list_id: u64, idx: u64) -> Option<binbuf::BufConst<E>> {
+        let item_id = self.item_ids(list_id).nth(idx as usize)?;
+        Some(unsafe { self.items.buf_unchecked(item_id) })
+    }
+
+    pub fn buf_mut(&mut self, list_id: u64, idx: u64) -> Option<binbuf::BufMut<E>> {
+        let item_id = self.item_ids(list_id).nth(idx as usize)?;
+        Some(unsafe { self.items.buf_mut_unchecked(item_id) })
+    }
+
+    // Items after the removed one are moved back by one.
+    // Returns true if item doesn't exist.
+    pub fn remove(&mut self, list_id: u64, idx: u64) -> Result<bool, RemoveError> {
+        let mut list = self.list(list_id);
+        if idx >= list.len {
+            return Ok(true);
+        }
+        let mut prev_block_id = None;
+        let mut block_id = list.first_block_id - 1;
+        for _ in 0 .. idx / BLOCK_LEN {
+            prev_block_id = Some(block_id);
+            block_id = self.next_block_id(block_id).unwrap();
+        }
+
+        // Items are moved into the hole one by one, the first item of the next block goes to the end of the current one.
+        let mut hole_idx = idx;
+        loop {
+            let block_end = ((hole_idx / BLOCK_LEN + 1) * BLOCK_LEN).min(list.len);
+            for item_idx in hole_idx + 1 .. block_end {
+                unsafe { self.items.copy(block_id * BLOCK_LEN + item_idx % BLOCK_LEN, block_id * BLOCK_LEN + (item_idx - 1) % BLOCK_LEN) };
+            }
+            if block_end == list.len {
+                break;
+            }
+            let next_block_id = self.next_block_id(block_id).unwrap();
+            unsafe { self.items.copy(next_block_id * BLOCK_LEN, (block_id + 1) * BLOCK_LEN - 1) };
+            prev_block_id = Some(block_id);
+            block_id = next_block_id;
+            hole_idx = block_end;
+        }
+
+        list.len -= 1;
+        if list.len % BLOCK_LEN == 0 {
+            match prev_block_id {
+                Some(prev_block_id) => {
+                    self.set_next_block_id(prev_block_id, None);
+                    list.last_block_id = prev_block_id + 1;
+                },
+                None => {
+                    list.first_block_id = 0;
+                    list.last_block_id = 0;
+                },
+            }
+            self.free_block(block_id).map_err(RemoveError::FreeBlock)?;
+        }
+        self.set_list(list_id, &list);
+        Ok(false)
+    }
+
+    pub fn iter(&self, list_id: u64) -> impl Iterator<Item = binbuf::BufConst<E>> + '_ {
+        self.item_ids(list_id).map(|item_id| unsafe { self.items.buf_unchecked(item_id) })
+    }
+}
+
+impl<E: binbuf::fixed::Decode> Value<E> {
+    pub fn get(&self, list_id: u64, idx: u64) -> Option<E> {
+        self.buf(list_id, idx).map(|buf| binbuf::fixed::decode::<E, _>(buf))
+    }
+}
@@ -0,0 +1,207 @@
+use std::fs::File;
+use binbuf::{fixed::Readable, Fixed as _};
+use super::OpenMode;
+
+binbuf::fixed! {
+    pub struct Header {
+        #[lens(buf_head)]
+        head: u64, // id of the slot with the front item
+        #[lens(buf_len)]
+        len: u64,
+        #[lens(buf_max_len)]
+        max_len: Option<u64>,
+    }
+    buf! { pub struct HeaderBuf<P>(Header, P); }
+
+    impl I for Header {
+        type Buf<P> = HeaderBuf<P>;
+    }
+    impl Code for Header {}
+}
+
+#[derive(Debug)]
+pub enum PushBackError {
+    AddSlots(super::fixed::ReserveError),
+}
+
+#[derive(Debug)]
+pub enum OpenError {
+    FixedOpen(super::fixed::OpenError),
+    SingleOpen(super::single::OpenError),
+}
+
+pub struct OpenFiles {
+    pub slots: File,
+    pub header: File,
+}
+
+pub struct OpenMaxMargins {
+    pub slots: u64,
+}
+
+pub struct OpenConfig {
+    pub mode: OpenMode,
+    pub files: OpenFiles,
+    pub max_margins: OpenMaxMargins,
+    // Only used for a new queue, existing one keeps its own.
+    pub max_len: Option<u64>,
+}
+
+// FIFO queue stored as a ring buffer, so slots of popped items are reused.
+// The file only grows when all slots are used.
+pub struct Value<E> {
+    slots: super::Fixed<E>,
+    header: super::Single<Header>,
+    head: u64,
+    len: u64,
+    max_len: Option<u64>,
+}
+
+impl<E: binbuf::Fixed> Value<E> {
+    pub unsafe fn open(OpenConfig { mode, files, max_margins, max_len }: OpenConfig) -> Result<Self, OpenError> {
+        let new_header = Header { head: 0, len: 0, max_len };
+        let header = super::Single::open(
+            match mode {
+                OpenMode::New => super::single::OpenMode::New(&new_header),
+                OpenMode::Existing => super::single::OpenMode::Existing,
+            },
+            files.header,
+        )
+            .map_err(OpenError::SingleOpen)?;
+        let Header { head, len, max_len } = header.get();
+
+        Ok(Self {
+            slots: super::Fixed::open(mode, files.slots, max_margins.slots).map_err(OpenError::FixedOpen)?,
+            header,
+            head,
+            len,
+            max_len,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn max_len(&self) -> Option<u64> {
+        self.max_len
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.max_len.is_some_and(|max_len| self.len >= max_len)
+    }
+
+    // Number of items which fit into the queue without growing the file.
+    pub fn capacity(&self) -> u64 {
+        self.slots.len()
+    }
+
+    fn set_head_and_len(&mut self, head: u64, len: u64) {
+        let header = self.header.buf_mut();
+        head.encode(Header::buf_head(header));
+        len.encode(Header::buf_len(header));
+        self.head = head;
+        self.len = len;
+    }
+
+    fn slot_id(&self, idx: u64) -> u64 {
+        (self.head + idx) % self.slots.len()
+    }
+
+    // Index 0 is the front item.
+    pub fn buf(&self, idx: u64) -> Option<binbuf::BufConst<E>> {
+        if idx < self.len {
+            Some(unsafe { self.slots.buf_unchecked(self.slot_id(idx)) })
+        } else {
+            None
+        }
+    }
+
+    pub fn buf_mut(&mut self, idx: u64) -> Option<binbuf::BufMut<E>> {
+        if idx < self.len {
+            let id = self.slot_id(idx);
+            Some(unsafe { self.slots.buf_mut_unchecked(id) })
+        } else {
+            None
+        }
+    }
+
+    pub fn peek_buf(&self) -> Option<binbuf::BufConst<E>> {
+        self.buf(0)
+    }
+
+    // Doubles the count of slots, but not past the max length.
+    // Slots before the head hold the last items, so they are moved to the added slots to keep items in order.
+    // If there are fewer added slots than that, the rest of them is shifted to the start of the file.
+    fn grow(&mut self) -> Result<(), PushBackError> {
+        let slots_len = self.slots.len();
+        let added_len = self.max_len
+            .map_or(slots_len, |max_len| slots_len.min(max_len.saturating_sub(slots_len)))
+            .max(1);
+        let first_id = self.slots.add_zeroed(added_len).map_err(PushBackError::AddSlots)?;
+        let moved_len = self.head.min(added_len);
+        for id in 0 .. moved_len {
+            unsafe { self.slots.copy(id, first_id + id) };
+        }
+        for id in moved_len .. self.head {
+            unsafe { self.slots.copy(id, id - moved_len) };
+        }
+        Ok(())
+    }
+
+    // Returns true if the queue is full.
+    pub fn push_back(&mut self, entry: impl Readable<E>) -> Result<bool, PushBackError> {
+        if self.is_full() {
+            return Ok(true);
+        }
+        if self.len == self.slots.len() {
+            self.grow()?;
+        }
+        let id = self.slot_id(self.len);
+        entry.write_to(unsafe { self.slots.buf_mut_unchecked(id) });
+        self.set_head_and_len(self.head, self.len + 1);
+        Ok(false)
+    }
+
+    // Returns true if the queue is empty.
+    pub fn remove_front(&mut self) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        if self.len == 1 {
+            self.set_head_and_len(0, 0);
+        } else {
+            self.set_head_and_len(self.slot_id(1), self.len - 1);
+        }
+        false
+    }
+
+    pub fn clear(&mut self) {
+        self.set_head_and_len(0, 0);
+    }
+
+    // Iterates items from the front.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = binbuf::BufConst<E>> + '_ {
+        (0 .. self.len).map(|idx| unsafe { self.slots.buf_unchecked(self.slot_id(idx)) })
+    }
+}
+
+impl<E: binbuf::fixed::Decode> Value<E> {
+    pub fn get(&self, idx: u64) -> Option<E> {
+        self.buf(idx).map(|buf| binbuf::fixed::decode::<E, _>(buf))
+    }
+
+    pub fn peek(&self) -> Option<E> {
+        self.get(0)
+    }
+
+    pub fn pop_front(&mut self) -> Option<E> {
+        let entry = self.peek()?;
+        self.remove_front();
+        Some(entry)
+    }
+}
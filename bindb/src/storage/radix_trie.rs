@@ -0,0 +1,493 @@
+use std::fs::File;
+use binbuf::{bytes_ptr, fixed::Readable, Entry, Fixed as _};
+use super::{dynamic::EntryId, OpenMode};
+
+const ROOT_ID: u64 = 0;
+
+binbuf::fixed! {
+    pub struct Node<V> {
+        #[lens(buf_label_id)]
+        label_id: EntryId,
+        #[lens(buf_first_child_id)]
+        first_child_id: u64, // id + 1, 0 if there is none
+        #[lens(buf_next_sibling_id)]
+        next_sibling_id: u64, // id + 1, 0 if there is none
+        #[lens(buf_has_value)]
+        has_value: bool,
+        #[lens(buf_value)]
+        value: V,
+    }
+    buf! { pub struct NodeBuf<P, V: binbuf::Fixed>(Node<V>, P); }
+
+    impl<V: binbuf::Fixed> I for Node<V> {
+        type Buf<P> = NodeBuf<P, V>;
+    }
+
+    impl<V: binbuf::Fixed> Encode for Node<V> {}
+    impl<V: binbuf::fixed::Decode> Decode for Node<V> {}
+}
+
+binbuf::fixed! {
+    pub struct Header {
+        #[lens(buf_len)]
+        len: u64,
+    }
+    buf! { pub struct HeaderBuf<P>(Header, P); }
+
+    impl I for Header {
+        type Buf<P> = HeaderBuf<P>;
+    }
+    impl Code for Header {}
+}
+
+#[derive(Debug)]
+pub enum AddNodeError {
+    AddLabel(super::dynamic::AddError),
+    AddNode(super::fixed::AddError),
+    RemoveLastFreeId(super::fixed::RemoveLastError),
+}
+
+#[derive(Debug)]
+pub enum FreeNodeError {
+    RemoveLabel(super::dynamic::RemoveError),
+    RemoveIfLast(super::fixed::RemoveLastError),
+    AddFreeId(super::fixed::AddError),
+}
+
+#[derive(Debug)]
+pub enum AddError {
+    AddNode(AddNodeError),
+    ReplaceLabel(super::dynamic::ReplaceError),
+}
+
+#[derive(Debug)]
+pub enum RemoveError {
+    FreeNode(FreeNodeError),
+    ReplaceLabel(super::dynamic::ReplaceError),
+}
+
+#[derive(Debug)]
+pub enum OpenError {
+    FixedOpen(super::fixed::OpenError),
+    DynamicOpen(super::dynamic::OpenError),
+    SingleOpen(super::single::OpenError),
+    AddRootNode(AddNodeError),
+}
+
+pub struct OpenFiles {
+    pub nodes: File,
+    pub free_ids: File,
+    pub header: File,
+    pub label_entries: File,
+    pub label_free_locations: File,
+}
+
+pub struct OpenMaxMargins {
+    pub nodes: u64,
+    pub free_ids: u64,
+    pub label_entries: u64,
+    pub label_free_locations: u64,
+}
+
+pub struct OpenConfig {
+    pub mode: OpenMode,
+    pub files: OpenFiles,
+    pub max_margins: OpenMaxMargins,
+}
+
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+// Iterates items under a prefix in order of keys.
+pub struct PrefixIter<'a, V> {
+    trie: &'a Value<V>,
+    start_id: u64,
+    stack: Vec<(u64, usize)>, // node id and length of the key before its label
+    key: Vec<u8>,
+}
+
+impl<'a, V: binbuf::Fixed> Iterator for PrefixIter<'a, V> {
+    type Item = (Vec<u8>, binbuf::BufConst<V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((id, len)) = self.stack.pop() {
+            self.key.truncate(len);
+            self.key.extend_from_slice(self.trie.label(id));
+            // Siblings of the start node don't share the prefix.
+            if id != self.start_id {
+                if let Some(next_id) = self.trie.next_sibling_id(id) {
+                    self.stack.push((next_id, len));
+                }
+            }
+            if let Some(child_id) = self.trie.first_child_id(id) {
+                self.stack.push((child_id, self.key.len()));
+            }
+            if self.trie.has_value(id) {
+                return Some((self.key.clone(), self.trie.value_buf(id)));
+            }
+        }
+        None
+    }
+}
+
+// Radix trie map with keys of bytes and values of a fixed size.
+// Every node holds a part of the key (label) which is stored in a dynamic storage, children of a node are sorted by their labels.
+// Nodes without a value always have at least two children, except the root.
+pub struct Value<V> {
+    nodes: super::Fixed<Node<V>>,
+    free_ids: super::Fixed<u64>,
+    labels: super::Dynamic<bytes_ptr::Const>,
+    header: super::Single<Header>,
+    len: u64,
+}
+
+impl<V: binbuf::Fixed> Value<V> {
+    pub unsafe fn open(OpenConfig { mode, files, max_margins }: OpenConfig) -> Result<Self, OpenError> {
+        let header = super::Single::open(
+            match mode {
+                OpenMode::New => super::single::OpenMode::New(&Header { len: 0 }),
+                OpenMode::Existing => super::single::OpenMode::Existing,
+            },
+            files.header,
+        )
+            .map_err(OpenError::SingleOpen)?;
+        let Header { len } = header.get();
+
+        let mut value = Self {
+            nodes: super::Fixed::open(mode, files.nodes, max_margins.nodes).map_err(OpenError::FixedOpen)?,
+            free_ids: super::Fixed::open(mode, files.free_ids, max_margins.free_ids).map_err(OpenError::FixedOpen)?,
+            labels: super::Dynamic::open(super::dynamic::OpenConfig {
+                mode,
+                files: super::dynamic::OpenFiles {
+                    entries: files.label_entries,
+                    free_locations: files.label_free_locations,
+                    boundaries: None,
+                },
+                max_margins: super::dynamic::OpenMaxMargins {
+                    entries: max_margins.label_entries,
+                    free_locations: max_margins.label_free_locations,
+                    boundaries: 0,
+                },
+            }).map_err(OpenError::DynamicOpen)?,
+            header,
+            len,
+        };
+        if let OpenMode::New = mode {
+            value.add_node(&[]).map_err(OpenError::AddRootNode)?;
+        }
+        Ok(value)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn set_len(&mut self, len: u64) {
+        len.encode(Header::buf_len(self.header.buf_mut()));
+        self.len = len;
+    }
+
+    fn node_buf(&self, id: u64) -> binbuf::BufConst<Node<V>> {
+        unsafe { self.nodes.buf_unchecked(id) }
+    }
+
+    fn node_buf_mut(&mut self, id: u64) -> binbuf::BufMut<Node<V>> {
+        unsafe { self.nodes.buf_mut_unchecked(id) }
+    }
+
+    fn label_id(&self, id: u64) -> EntryId {
+        binbuf::fixed::decode::<EntryId, _>(Node::<V>::buf_label_id(self.node_buf(id)))
+    }
+
+    fn label(&self, id: u64) -> &[u8] {
+        let label_buf = unsafe { self.labels.buf_unchecked(self.label_id(id)) };
+        binbuf::dynamic::decode::<bytes_ptr::Const>(label_buf).0.slice()
+    }
+
+    fn first_child_id(&self, id: u64) -> Option<u64> {
+        binbuf::fixed::decode::<u64, _>(Node::<V>::buf_first_child_id(self.node_buf(id))).checked_sub(1)
+    }
+
+    fn next_sibling_id(&self, id: u64) -> Option<u64> {
+        binbuf::fixed::decode::<u64, _>(Node::<V>::buf_next_sibling_id(self.node_buf(id))).checked_sub(1)
+    }
+
+    fn set_first_child_id(&mut self, id: u64, child_id: Option<u64>) {
+        child_id.map_or(0, |id| id + 1).encode(Node::<V>::buf_first_child_id(self.node_buf_mut(id)));
+    }
+
+    fn set_next_sibling_id(&mut self, id: u64, sibling_id: Option<u64>) {
+        sibling_id.map_or(0, |id| id + 1).encode(Node::<V>::buf_next_sibling_id(self.node_buf_mut(id)));
+    }
+
+    fn has_value(&self, id: u64) -> bool {
+        binbuf::fixed::decode::<bool, _>(Node::<V>::buf_has_value(self.node_buf(id)))
+    }
+
+    fn value_buf(&self, id: u64) -> binbuf::BufConst<V> {
+        Node::<V>::buf_value(self.node_buf(id))
+    }
+
+    fn set_value(&mut self, id: u64, value: impl Readable<V>) {
+        let node = self.node_buf_mut(id);
+        true.encode(Node::<V>::buf_has_value(node));
+        value.write_to(Node::<V>::buf_value(node));
+    }
+
+    fn children_ids(&self, id: u64) -> impl Iterator<Item = u64> + '_ {
+        std::iter::successors(self.first_child_id(id), |&id| self.next_sibling_id(id))
+    }
+
+    fn only_child_id(&self, id: u64) -> Option<u64> {
+        self.first_child_id(id).filter(|&child_id| self.next_sibling_id(child_id).is_none())
+    }
+
+    // Returns id of the child whose label starts with the byte, and id of the child before the place where it is or should be.
+    fn find_child(&self, id: u64, byte: u8) -> (Option<u64>, Option<u64>) {
+        let mut prev_id = None;
+        for child_id in self.children_ids(id) {
+            let child_byte = self.label(child_id)[0];
+            if child_byte == byte {
+                return (prev_id, Some(child_id));
+            }
+            if child_byte > byte {
+                break;
+            }
+            prev_id = Some(child_id);
+        }
+        (prev_id, None)
+    }
+
+    // Points the previous child, or the parent if there is none, to the node.
+    fn set_prev_link(&mut self, parent_id: u64, prev_id: Option<u64>, id: Option<u64>) {
+        match prev_id {
+            Some(prev_id) => self.set_next_sibling_id(prev_id, id),
+            None => self.set_first_child_id(parent_id, id),
+        }
+    }
+
+    fn unlink(&mut self, parent_id: u64, id: u64) {
+        let prev_id = self.children_ids(parent_id).take_while(|&child_id| child_id != id).last();
+        self.set_prev_link(parent_id, prev_id, self.next_sibling_id(id));
+    }
+
+    // Adds a node without children and value.
+    fn add_node(&mut self, label: &[u8]) -> Result<u64, AddNodeError> {
+        let label_id = self.labels.add(&unsafe { bytes_ptr::Const::from_slice(label) }).map_err(AddNodeError::AddLabel)?;
+        let mut node = vec![0u8; Node::<V>::LEN];
+        let node_buf = unsafe { Node::<V>::buf(bytes_ptr::Mut::from_slice(&mut node)) };
+        label_id.encode(Node::<V>::buf_label_id(node_buf));
+
+        match self.free_ids.last_buf() {
+            Some(id_buf) => {
+                let id = binbuf::fixed::decode::<u64, _>(id_buf);
+                node_buf.write_to(self.node_buf_mut(id));
+                self.free_ids.remove_last().map_err(AddNodeError::RemoveLastFreeId)?;
+                Ok(id)
+            },
+            None => self.nodes.add(node_buf).map_err(AddNodeError::AddNode),
+        }
+    }
+
+    fn free_node(&mut self, id: u64) -> Result<(), FreeNodeError> {
+        unsafe { self.labels.remove(self.label_id(id)) }.map_err(FreeNodeError::RemoveLabel)?;
+        if self.nodes.remove_if_last(id).map_err(FreeNodeError::RemoveIfLast)? {
+            self.free_ids.add(&id).map_err(FreeNodeError::AddFreeId)?;
+        }
+        Ok(())
+    }
+
+    // Label mustn't point into the labels storage.
+    fn replace_label(&mut self, id: u64, label: &[u8]) -> Result<(), super::dynamic::ReplaceError> {
+        let label_id = self.label_id(id);
+        let new_label_id = unsafe { self.labels.replace(label_id, &bytes_ptr::Const::from_slice(label)) }?;
+        new_label_id.encode(Node::<V>::buf_label_id(self.node_buf_mut(id)));
+        Ok(())
+    }
+
+    // Node takes label, value and children of its only child, which is then freed.
+    fn merge_with_child(&mut self, id: u64, child_id: u64) -> Result<(), RemoveError> {
+        let mut label = self.label(id).to_vec();
+        label.extend_from_slice(self.label(child_id));
+        self.replace_label(id, &label).map_err(RemoveError::ReplaceLabel)?;
+
+        let child = self.node_buf(child_id);
+        let node = self.node_buf_mut(id);
+        binbuf::fixed::buf_copy_to::<bool>(Node::<V>::buf_has_value(child), Node::<V>::buf_has_value(node));
+        binbuf::fixed::buf_copy_to::<V>(Node::<V>::buf_value(child), Node::<V>::buf_value(node));
+        self.set_first_child_id(id, self.first_child_id(child_id));
+        self.free_node(child_id).map_err(RemoveError::FreeNode)
+    }
+
+    // Returns ids of nodes from the root to the node with the key, if there is such node.
+    fn find_path(&self, key: &[u8]) -> Option<Vec<u64>> {
+        let mut path = vec![ROOT_ID];
+        let mut pos = 0;
+        while pos < key.len() {
+            let child_id = self.find_child(*path.last().unwrap(), key[pos]).1?;
+            let label = self.label(child_id);
+            if !key[pos ..].starts_with(label) {
+                return None;
+            }
+            pos += label.len();
+            path.push(child_id);
+        }
+        Some(path)
+    }
+
+    fn find(&self, key: &[u8]) -> Option<u64> {
+        self.find_path(key)?.last().copied().filter(|&id| self.has_value(id))
+    }
+
+    pub fn contains_key(&self, key: impl AsRef<[u8]>) -> bool {
+        self.find(key.as_ref()).is_some()
+    }
+
+    pub fn buf(&self, key: impl AsRef<[u8]>) -> Option<binbuf::BufConst<V>> {
+        self.find(key.as_ref()).map(|id| self.value_buf(id))
+    }
+
+    pub fn buf_mut(&mut self, key: impl AsRef<[u8]>) -> Option<binbuf::BufMut<V>> {
+        self.find(key.as_ref()).map(|id| Node::<V>::buf_value(self.node_buf_mut(id)))
+    }
+
+    // Returns true if item already exists.
+    pub fn add(&mut self, key: impl AsRef<[u8]>, value: impl Readable<V>) -> Result<bool, AddError> {
+        let key = key.as_ref();
+        let (mut id, mut pos) = (ROOT_ID, 0);
+        while pos < key.len() {
+            let (prev_id, child_id) = self.find_child(id, key[pos]);
+            let Some(child_id) = child_id else {
+                let new_id = self.add_node(&key[pos ..]).map_err(AddError::AddNode)?;
+                let next_id = match prev_id {
+                    Some(prev_id) => self.next_sibling_id(prev_id),
+                    None => self.first_child_id(id),
+                };
+                self.set_next_sibling_id(new_id, next_id);
+                self.set_prev_link(id, prev_id, Some(new_id));
+                id = new_id;
+                break;
+            };
+
+            let label = self.label(child_id);
+            let (label_len, common_len) = (label.len(), common_prefix_len(label, &key[pos ..]));
+            if common_len < label_len {
+                // Label is split in two, its common part becomes a new node in place of the child.
+                let (head, tail) = (label[.. common_len].to_vec(), label[common_len ..].to_vec());
+                let mid_id = self.add_node(&head).map_err(AddError::AddNode)?;
+                self.replace_label(child_id, &tail).map_err(AddError::ReplaceLabel)?;
+                self.set_next_sibling_id(mid_id, self.next_sibling_id(child_id));
+                self.set_next_sibling_id(child_id, None);
+                self.set_first_child_id(mid_id, Some(child_id));
+                self.set_prev_link(id, prev_id, Some(mid_id));
+                id = mid_id;
+            } else {
+                id = child_id;
+            }
+            pos += common_len;
+        }
+
+        if self.has_value(id) {
+            return Ok(true);
+        }
+        self.set_value(id, value);
+        self.set_len(self.len + 1);
+        Ok(false)
+    }
+
+    // Returns true if item doesn't exist.
+    pub fn remove(&mut self, key: impl AsRef<[u8]>) -> Result<bool, RemoveError> {
+        let Some(path) = self.find_path(key.as_ref()) else {
+            return Ok(true);
+        };
+        let id = *path.last().unwrap();
+        if !self.has_value(id) {
+            return Ok(true);
+        }
+        false.encode(Node::<V>::buf_has_value(self.node_buf_mut(id)));
+        self.set_len(self.len - 1);
+
+        if id == ROOT_ID {
+            return Ok(false);
+        }
+        if self.first_child_id(id).is_none() {
+            let parent_id = path[path.len() - 2];
+            self.unlink(parent_id, id);
+            self.free_node(id).map_err(RemoveError::FreeNode)?;
+            if parent_id != ROOT_ID && !self.has_value(parent_id) {
+                if let Some(child_id) = self.only_child_id(parent_id) {
+                    self.merge_with_child(parent_id, child_id)?;
+                }
+            }
+        } else if let Some(child_id) = self.only_child_id(id) {
+            self.merge_with_child(id, child_id)?;
+        }
+        Ok(false)
+    }
+
+    // Iterates items whose keys start with the prefix, in order of keys.
+    pub fn prefix_iter(&self, prefix: impl AsRef<[u8]>) -> PrefixIter<'_, V> {
+        let prefix = prefix.as_ref();
+        let (mut id, mut base_len, mut pos) = (ROOT_ID, 0, 0);
+        // Prefix may end in the middle of a label, then all items of that node match.
+        let found = loop {
+            if pos == prefix.len() {
+                break true;
+            }
+            let Some(child_id) = self.find_child(id, prefix[pos]).1 else {
+                break false;
+            };
+            let label = self.label(child_id);
+            let common_len = common_prefix_len(label, &prefix[pos ..]);
+            if common_len < label.len() && pos + common_len < prefix.len() {
+                break false;
+            }
+            (id, base_len, pos) = (child_id, pos, pos + common_len);
+        };
+        PrefixIter {
+            trie: self,
+            start_id: id,
+            stack: if found { vec![(id, base_len)] } else { Vec::new() },
+            key: prefix[.. base_len].to_vec(),
+        }
+    }
+
+    pub fn iter(&self) -> PrefixIter<'_, V> {
+        self.prefix_iter(b"")
+    }
+
+    // Returns length of the longest key which is a prefix of the given one, and its value.
+    pub fn longest_prefix_match(&self, key: impl AsRef<[u8]>) -> Option<(usize, binbuf::BufConst<V>)> {
+        let key = key.as_ref();
+        let (mut id, mut pos) = (ROOT_ID, 0);
+        let mut matched = None;
+        loop {
+            if self.has_value(id) {
+                matched = Some((pos, id));
+            }
+            if pos == key.len() {
+                break;
+            }
+            let Some(child_id) = self.find_child(id, key[pos]).1 else {
+                break;
+            };
+            let label = self.label(child_id);
+            if !key[pos ..].starts_with(label) {
+                break;
+            }
+            (id, pos) = (child_id, pos + label.len());
+        }
+        matched.map(|(len, id)| (len, self.value_buf(id)))
+    }
+}
+
+impl<V: binbuf::fixed::Decode> Value<V> {
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Option<V> {
+        self.buf(key).map(|buf| binbuf::fixed::decode::<V, _>(buf))
+    }
+}
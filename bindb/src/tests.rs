@@ -1,6 +1,7 @@
+use std::path::{Path, PathBuf};
 use rand::Rng;
 
-use crate::storage;
+use crate::storage::{self, OpenMode};
 
 macro_rules! open_file {
     ($name: expr) => {
@@ -8,10 +9,15 @@ macro_rules! open_file {
     };
 }
 
-fn init() {
-    std::fs::remove_dir_all("./local").unwrap();
-    std::fs::create_dir("./local").unwrap();
+// Every test gets its own directory, so that tests can run in parallel.
+fn init(name: &str) -> PathBuf {
+    let dir = Path::new("./local").join(name);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
     std::env::set_var("RUST_BACKTRACE", "1");
+    dir
 }
 
 binbuf::fixed! {
@@ -31,15 +37,66 @@ binbuf::fixed! {
     impl Code for TestEntry1 {}
 }
 
-// #[test]
+unsafe fn open_dynamic<E: binbuf::Dynamic>(dir: &Path, mode: OpenMode, with_boundaries: bool) -> storage::Dynamic<E> {
+    storage::Dynamic::open(storage::dynamic::OpenConfig {
+        mode,
+        files: storage::dynamic::OpenFiles {
+            entries: open_file!(dir.join("entries")),
+            free_locations: open_file!(dir.join("free_locations")),
+            boundaries: with_boundaries.then(|| open_file!(dir.join("boundaries"))),
+        },
+        max_margins: storage::dynamic::OpenMaxMargins {
+            entries: 10,
+            free_locations: 10,
+            boundaries: 10,
+        },
+    }).unwrap()
+}
+
+unsafe fn open_indexed_dynamic<E: binbuf::Dynamic>(dir: &Path, mode: OpenMode) -> storage::IndexedDynamic<E> {
+    storage::IndexedDynamic::open(storage::indexed_dynamic::OpenConfig {
+        mode,
+        files: storage::indexed_dynamic::OpenFiles {
+            raw_entries: open_file!(dir.join("raw_entries")),
+            raw_free_locations: open_file!(dir.join("raw_free_locations")),
+            indices: open_file!(dir.join("indices")),
+            free_ids: open_file!(dir.join("free_ids")),
+        },
+        max_margins: storage::indexed_dynamic::OpenMaxMargins {
+            raw_entries: 10,
+            raw_free_locations: 10,
+            indices: 10,
+            free_ids: 10,
+        },
+    }).unwrap()
+}
+
+unsafe fn open_binary_tree<I: storage::binary_tree::NodeId, K: binbuf::fixed::Decode + std::fmt::Debug, V: binbuf::Fixed, S: storage::binary_tree::NodeSize>(
+    dir: &Path,
+    mode: OpenMode,
+) -> storage::BinaryTree<I, K, V, S> {
+    storage::BinaryTree::open(binary_tree_config(dir, mode)).unwrap()
+}
+
+fn binary_tree_config(dir: &Path, mode: OpenMode) -> storage::binary_tree::OpenConfig {
+    storage::binary_tree::OpenConfig {
+        mode,
+        files: storage::binary_tree::OpenFiles {
+            nodes: open_file!(dir.join("nodes")),
+            free_ids: open_file!(dir.join("free_ids")),
+            header: open_file!(dir.join("header")),
+        },
+        max_margins: storage::binary_tree::OpenMaxMargins {
+            nodes: 10,
+            free_ids: 10,
+        },
+    }
+}
+
+#[test]
 pub fn fixed_test1() {
-    init();
-    let mut db = unsafe {
-        storage::Fixed::create(
-            open_file!("./local/fixed1"),
-            10
-        )
-    }.unwrap();
+    let dir = init("fixed_test1");
+    let mut db = unsafe { storage::Fixed::open(OpenMode::New, open_file!(dir.join("fixed")), 10) }.unwrap();
 
     let entry = TestEntry1 {
         idx: 5,
@@ -47,7 +104,6 @@ pub fn fixed_test1() {
     };
     let id = db.add(&entry).unwrap();
     let output_entry = db.get(id);
-    println!("{:?}", &output_entry);
     assert_eq!(entry, output_entry);
 
     db.remove_last().unwrap();
@@ -59,130 +115,322 @@ pub fn fixed_test1() {
     };
     let id = db.add(&entry).unwrap();
     let output_entry = db.get(id);
-    println!("{:?}", &output_entry);
     assert_eq!(entry, output_entry);
     assert_eq!(db.len(), 1);
 }
 
-// #[test]
+#[test]
+pub fn fixed_reserve_test() {
+    let dir = init("fixed_reserve_test");
+    let mut db = unsafe { storage::Fixed::<u64>::open(OpenMode::New, open_file!(dir.join("fixed")), 10) }.unwrap();
+    let file_len = || std::fs::metadata(dir.join("fixed")).unwrap().len();
+
+    // Max margin is reserved on top, so the file isn't extended on every call.
+    assert_eq!(db.add_zeroed(1).unwrap(), 0);
+    let reserved_file_len = file_len();
+    for id in 1 ..= 10 {
+        assert_eq!(db.add_zeroed(1).unwrap(), id);
+        assert_eq!(file_len(), reserved_file_len);
+    }
+    assert_eq!(db.add_zeroed(1).unwrap(), 11);
+    assert!(file_len() > reserved_file_len);
+    assert_eq!(db.len(), 12);
+    assert!(db.all_ids().all(|id| db.get(id) == 0));
+}
+
+unsafe fn open_bitmap(dir: &Path, name: &str, mode: OpenMode) -> storage::Bitmap {
+    storage::Bitmap::open(mode, open_file!(dir.join(name)), 10).unwrap()
+}
+
+#[test]
+pub fn bitmap_test() {
+    let dir = init("bitmap_test");
+    let mut a = unsafe { open_bitmap(&dir, "a", OpenMode::New) };
+    assert_eq!(a.count_ones(), 0);
+    assert_eq!(a.iter_ones().next(), None);
+
+    // Bits around word boundaries.
+    let bits = [0, 1, 63, 64, 65, 127, 128, 300];
+    for bit in bits {
+        a.set(bit).unwrap();
+    }
+    assert_eq!(a.count_ones(), bits.len() as u64);
+    assert_eq!(a.len(), 320);
+    assert_eq!(a.iter_ones().collect::<Vec<_>>(), bits);
+    assert_eq!(a.next_set_after(1), Some(63));
+    assert_eq!(a.next_set_after(63), Some(64));
+    assert_eq!(a.next_set_after(65), Some(127));
+    assert_eq!(a.next_set_after(128), Some(300));
+    assert_eq!(a.next_set_after(300), None);
+    assert_eq!(a.next_set_after(u64::MAX), None);
+    a.clear(64);
+    a.clear(1000);
+    assert!(!a.get(64) && !a.get(1000));
+    assert_eq!(a.next_set_after(63), Some(65));
+    assert_eq!(a.count_ones(), bits.len() as u64 - 1);
+    drop(a);
+
+    // Shorter bitmap: `and_with` clears bits past its end, `or_with` extends the longer one.
+    let mut a = unsafe { open_bitmap(&dir, "a", OpenMode::Existing) };
+    let mut b = unsafe { open_bitmap(&dir, "b", OpenMode::New) };
+    for bit in [1, 5, 63, 65] {
+        b.set(bit).unwrap();
+    }
+    assert_eq!(b.len(), 128);
+    let mut c = unsafe { open_bitmap(&dir, "c", OpenMode::New) };
+    c.or_with(&b).unwrap();
+    c.or_with(&a).unwrap();
+    assert_eq!(c.iter_ones().collect::<Vec<_>>(), [0, 1, 5, 63, 65, 127, 128, 300]);
+    a.and_with(&b);
+    assert_eq!(a.iter_ones().collect::<Vec<_>>(), [1, 63, 65]);
+    assert_eq!(a.len(), 320);
+    b.and_with(&c);
+    assert_eq!(b.iter_ones().collect::<Vec<_>>(), [1, 5, 63, 65]);
+    b.or_with(&c).unwrap();
+    assert_eq!(b.len(), 320);
+    assert_eq!(b.count_ones(), 8);
+}
+
+#[test]
 pub fn dynamic_test1() {
-    init();
-    let mut db = unsafe {
-        storage::Dynamic::<String>::create(
-            storage::Fixed::create(
-                open_file!("./local/dynamic1_free_locations"),
-                10,
-            ).unwrap(),
-            open_file!("./local/dynamic1"),
-            10,
-        )
-    }.unwrap();
+    let dir = init("dynamic_test1");
+    let mut db = unsafe { open_dynamic::<String>(&dir, OpenMode::New, true) };
 
     let entry = "Hello from bindb!".to_string();
     let id = db.add(&entry).unwrap();
     let id1 = id;
-    let out = unsafe { db.get(id) };
-    println!("{}", &out);
-    assert_eq!(&entry, &out);
+    assert_eq!(&entry, &db.get(id).unwrap());
 
     let entry = "What is up everyone? I'm feeling good today. How are you?".to_string();
     let id = db.add(&entry).unwrap();
-    let out = unsafe { db.get(id) };
-    println!("{}", &out);
-    assert_eq!(&entry, &out);
+    assert_eq!(&entry, &db.get(id).unwrap());
 
     unsafe { db.remove(id1) }.unwrap();
+    assert!(matches!(db.get(id1), Err(storage::dynamic::GetError::InvalidId)));
 
     let entry = "Hello again!".to_string();
     let id = db.add(&entry).unwrap();
-    let out = unsafe { db.get(id1) };
-    println!("{}", &out);
-    assert_eq!(&entry, &out);
+    assert_eq!(id.0, id1.0);
+    assert_eq!(&entry, &db.get(id1).unwrap());
 }
 
-// #[test]
-pub fn indexed_dynamic_test1() {
-    init();
-    let mut db = unsafe {
-        storage::IndexedDynamic::<String>::open(
-            storage::Dynamic::create(
-                storage::Fixed::create(
-                    open_file!("./local/indexed_dynamic1_free_locations"),
-                    10,
-                ).unwrap(),
-                open_file!("./local/indexed_dynamic1"),
-                10,
-            ).unwrap(),
-            storage::Fixed::create(
-                open_file!("./local/indexed_dynamic1_indices"),
-                10
-            ).unwrap(),
-            storage::Fixed::create(
-                open_file!("./local/indexed_dynamic1_free_ids"),
-                10
-            ).unwrap(),
-        ).unwrap()
+#[test]
+pub fn dynamic_boundaries_test() {
+    let dir = init("dynamic_boundaries_test");
+    let entries = ["first", "second entry", "third"].map(String::from);
+    let ids = {
+        let mut db = unsafe { open_dynamic::<String>(&dir, OpenMode::New, false) };
+        let ids = entries.iter().map(|entry| db.add(entry).unwrap()).collect::<Vec<_>>();
+        assert!(matches!(db.get(ids[0]), Err(storage::dynamic::GetError::BoundariesNotTracked)));
+        unsafe { db.remove(ids[1]) }.unwrap();
+        ids
+    };
+
+    // Boundaries of entries stored without them are rebuilt on open.
+    let db = unsafe { open_dynamic::<String>(&dir, OpenMode::Existing, true) };
+    assert_eq!(db.get(ids[0]).unwrap(), entries[0]);
+    assert_eq!(db.get(ids[2]).unwrap(), entries[2]);
+    assert!(matches!(db.get(ids[1]), Err(storage::dynamic::GetError::InvalidId)));
+    assert!(matches!(db.get(storage::dynamic::EntryId(ids[0].0 + 1)), Err(storage::dynamic::GetError::InvalidId)));
+    drop(db);
+
+    let db = unsafe { open_dynamic::<String>(&dir, OpenMode::Existing, true) };
+    assert_eq!(db.get(ids[2]).unwrap(), entries[2]);
+    assert_eq!(db.iter().count(), 2);
+    drop(db);
+
+    // Boundaries which went stale while entries were changed without them are rebuilt too.
+    let id = {
+        let mut db = unsafe { open_dynamic::<String>(&dir, OpenMode::Existing, false) };
+        unsafe { db.remove(ids[0]) }.unwrap();
+        db.add(&entries.concat().repeat(2)).unwrap()
     };
+    assert!(id.0 > ids[2].0);
+    let db = unsafe { open_dynamic::<String>(&dir, OpenMode::Existing, true) };
+    assert!(matches!(db.get(ids[0]), Err(storage::dynamic::GetError::InvalidId)));
+    assert_eq!(db.get(id).unwrap(), entries.concat().repeat(2));
+    assert_eq!(db.get(ids[2]).unwrap(), entries[2]);
+}
+
+#[test]
+pub fn dynamic_len_test() {
+    let dir = init("dynamic_len_test");
+    let entries = ["first", "second entry", "third"].map(String::from);
+    let ids = {
+        let mut db = unsafe { open_dynamic::<String>(&dir, OpenMode::New, false) };
+        let ids = entries.iter().map(|entry| db.add(entry).unwrap()).collect::<Vec<_>>();
+        unsafe { db.remove(ids[0]) }.unwrap();
+        assert_eq!(db.len(), 2);
+        ids
+    };
+
+    // Header written before entry count was stored has zero in its place.
+    {
+        use std::io::{Seek, Write};
+        let mut file = open_file!(dir.join("entries"));
+        file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        file.write_all(&[0; 8]).unwrap();
+    }
+
+    let mut db = unsafe { open_dynamic::<String>(&dir, OpenMode::Existing, false) };
+    assert_eq!(db.len(), 2);
+    unsafe { db.remove(ids[1]) }.unwrap();
+    assert_eq!(db.len(), 1);
+    drop(db);
+
+    let db = unsafe { open_dynamic::<String>(&dir, OpenMode::Existing, false) };
+    assert_eq!(db.len(), 1);
+}
+
+#[test]
+pub fn dynamic_replace_test() {
+    let dir = init("dynamic_replace_test");
+    let mut db = unsafe { open_dynamic::<String>(&dir, OpenMode::New, true) };
+
+    let long = "A long entry which is going to be shrunk in place.".to_string();
+    let id1 = db.add(&long).unwrap();
+    let id2 = db.add(&"second".to_string()).unwrap();
+
+    // Shrinking keeps the id and frees the tail of the old entry.
+    let short = "short".to_string();
+    assert_eq!(unsafe { db.replace(id1, &short) }.unwrap().0, id1.0);
+    assert_eq!(db.get(id1).unwrap(), short);
+    assert_eq!(db.free_locations_len(), 1);
+
+    // Freed tail is reused by the next entry that fits.
+    let id3 = db.add(&"tail".to_string()).unwrap();
+    assert!(id1.0 < id3.0 && id3.0 < id2.0);
+    assert_eq!(db.get(id3).unwrap(), "tail");
+    assert_eq!(db.get(id1).unwrap(), short);
+    assert_eq!(db.get(id2).unwrap(), "second");
+
+    // Growing moves the entry to a new location.
+    let new_id1 = unsafe { db.replace(id1, &long) }.unwrap();
+    assert_ne!(new_id1.0, id1.0);
+    assert_eq!(db.get(new_id1).unwrap(), long);
+    assert!(matches!(db.get(id1), Err(storage::dynamic::GetError::InvalidId)));
+    assert_eq!(db.get(id2).unwrap(), "second");
+    assert_eq!(db.get(id3).unwrap(), "tail");
+    assert_eq!(db.len(), 3);
+}
+
+#[test]
+pub fn indexed_dynamic_iter_test() {
+    let dir = init("indexed_dynamic_iter_test");
+    let mut db = unsafe { open_indexed_dynamic::<String>(&dir, OpenMode::New) };
+
+    let ids = ["first", "second", "third"].map(|entry| db.add(&entry.to_string()).unwrap());
+    assert_eq!(db.len(), 3);
+
+    assert!(!db.remove(ids[1]).unwrap());
+    assert!(db.contains(ids[0]) && !db.contains(ids[1]) && db.contains(ids[2]));
+    assert_eq!(db.len(), 2);
+    assert_eq!(db.ids().collect::<Vec<_>>(), [ids[0], ids[2]]);
+    assert_eq!(
+        db.iter().map(|(id, buf)| (id, binbuf::dynamic::decode::<String>(buf).0)).collect::<Vec<_>>(),
+        [(ids[0], "first".to_string()), (ids[2], "third".to_string())]
+    );
+
+    // Reused slot is listed under its new id only.
+    let id = db.add(&"fourth".to_string()).unwrap();
+    assert_eq!(id.index, ids[1].index);
+    assert!(db.contains(id) && !db.contains(ids[1]));
+    assert_eq!(db.len(), 3);
+    assert_eq!(db.ids().collect::<Vec<_>>(), [ids[0], id, ids[2]]);
+    assert_eq!(
+        db.iter().map(|(_, buf)| binbuf::dynamic::decode::<String>(buf).0).collect::<Vec<_>>(),
+        ["first", "fourth", "third"]
+    );
+}
+
+#[test]
+pub fn indexed_dynamic_stale_id_test() {
+    let dir = init("indexed_dynamic_stale_id_test");
+    let mut db = unsafe { open_indexed_dynamic::<String>(&dir, OpenMode::New) };
+
+    let old_id = db.add(&"old".to_string()).unwrap();
+    assert!(!db.remove(old_id).unwrap());
+    let new_id = db.add(&"new".to_string()).unwrap();
+
+    // Slot is reused, but the old id doesn't refer to the new entry.
+    assert_eq!(new_id.index, old_id.index);
+    assert_ne!(new_id.generation, old_id.generation);
+    assert!(!db.contains(old_id));
+    assert_eq!(db.get(old_id), None);
+    assert!(db.set(old_id, &"changed".to_string()).unwrap());
+    assert!(db.remove(old_id).unwrap());
+    assert_eq!(db.get(new_id), Some("new".to_string()));
+}
+
+#[test]
+pub fn indexed_dynamic_replace_test() {
+    let dir = init("indexed_dynamic_replace_test");
+    let mut db = unsafe { open_indexed_dynamic::<String>(&dir, OpenMode::New) };
+
+    let long = "A long entry which is going to be shrunk in place.".to_string();
+    let id1 = db.add(&long).unwrap();
+    let id2 = db.add(&"second".to_string()).unwrap();
+
+    assert!(!db.set(id1, &"short".to_string()).unwrap());
+    assert_eq!(db.get(id1), Some("short".to_string()));
+
+    // Entry has to be moved, but the id stays the same.
+    let longer = format!("{long} Now it's even longer, so it doesn't fit.");
+    assert_eq!(db.replace(id1, &longer).unwrap(), Some("short".to_string()));
+    assert_eq!(db.get(id1), Some(longer.clone()));
+    assert_eq!(db.get(id2), Some("second".to_string()));
+
+    assert!(!db.remove(id2).unwrap());
+    assert_eq!(db.replace(id2, &long).unwrap(), None);
+    assert!(db.set(id2, &long).unwrap());
+    drop(db);
+
+    let db = unsafe { open_indexed_dynamic::<String>(&dir, OpenMode::Existing) };
+    assert_eq!(db.get(id1), Some(longer));
+}
+
+#[test]
+pub fn indexed_dynamic_test1() {
+    let dir = init("indexed_dynamic_test1");
+    let mut db = unsafe { open_indexed_dynamic::<String>(&dir, OpenMode::New) };
 
     let entry = "What is up everyone? I'm feeling good today. How are you?".to_string();
     let id = db.add(&entry).unwrap();
-    let out = unsafe { db.get(id) };
-    println!("{}", &out);
-    assert_eq!(&entry, &out);
+    assert_eq!(db.get(id), Some(entry));
 
-    unsafe { db.remove(id).unwrap() };
+    assert!(!db.remove(id).unwrap());
+    assert_eq!(db.get(id), None);
 }
 
 #[test]
 pub fn indexed_dynamic_test2() {
-    init();
-    let mut db = unsafe {
-        storage::IndexedDynamic::<String>::open(
-            storage::Dynamic::create(
-                storage::Fixed::create(
-                    open_file!("./local/indexed_dynamic1_free_locations"),
-                    10,
-                ).unwrap(),
-                open_file!("./local/indexed_dynamic1"),
-                10,
-            ).unwrap(),
-            storage::Fixed::create(
-                open_file!("./local/indexed_dynamic1_indices"),
-                10
-            ).unwrap(),
-            storage::Fixed::create(
-                open_file!("./local/indexed_dynamic1_free_ids"),
-                10
-            ).unwrap(),
-        ).unwrap()
-    };
+    let dir = init("indexed_dynamic_test2");
+    let mut db = unsafe { open_indexed_dynamic::<String>(&dir, OpenMode::New) };
 
-    for i in 0 .. 10 {
-        db.add(&"Some value!".to_string()).unwrap();
+    let mut ids = Vec::new();
+    for _ in 0 .. 10 {
+        ids.push(db.add(&"Some value!".to_string()).unwrap());
     }
-    for i in 0 .. 10 {
-        unsafe { db.remove(i) }.unwrap();
+    for id in ids.drain(..) {
+        db.remove(id).unwrap();
     }
-    let mut ids = Vec::new();
-    for i in 0 .. 10 {
+    for _ in 0 .. 10 {
         ids.push(db.add(&"Some value!".to_string()).unwrap());
     }
+    drop(db);
+
+    let db = unsafe { open_indexed_dynamic::<String>(&dir, OpenMode::Existing) };
     for id in ids {
-        assert_eq!(db.get(id), "Some value!".to_string());
+        assert_eq!(db.get(id), Some("Some value!".to_string()));
     }
-
-    // let id1 = db.add(&"What's up?".to_string()).unwrap();
-    // let id2 = db.add(&"Loli".to_string()).unwrap();
-    // unsafe { db.remove(id1).unwrap() };
-    // unsafe { db.remove(id2).unwrap() };
 }
 
-// #[test]
+#[test]
 pub fn single_test1() {
-    init();
+    let dir = init("single_test1");
     let entry = "What's up!?".to_string();
     let mut db = unsafe {
-        storage::Single::create(open_file!("./local/single1"), &entry).unwrap()
+        storage::Single::open(storage::single::OpenMode::New(&entry), open_file!(dir.join("single"))).unwrap()
     };
 
     assert_eq!(&db.get(), &entry);
@@ -192,18 +440,11 @@ pub fn single_test1() {
     assert_eq!(&db.get(), &entry);
 }
 
-// #[test]
+#[test]
 pub fn binary_tree_test1() {
-    use binbuf::impls::{ArbNum, arb_num};
-    init();
-    let entry = "What's up!?".to_string();
-    let mut db = unsafe {
-        storage::BinaryTree::<ArbNum<4, u64>, i32, TestEntry1>::create(
-            storage::Fixed::create(open_file!("./local/binary_tree1_nodes"), 10).unwrap(),
-            storage::Fixed::create(open_file!("./local/binary_tree_free_ids"), 10).unwrap(),
-            open_file!("./local/binary_tree1_header")
-        ).unwrap()
-    };
+    use binbuf::impls::ArbNum;
+    let dir = init("binary_tree_test1");
+    let mut db = unsafe { open_binary_tree::<ArbNum<4, u64>, i32, TestEntry1, ()>(&dir, OpenMode::New) };
     let entry = TestEntry1 {
         idx: 999,
         opt: Some(true),
@@ -214,28 +455,21 @@ pub fn binary_tree_test1() {
     assert_eq!(db.get(&584), Some(entry.clone()));
 
     db.add(&103, &entry).unwrap();
-    db.remove(&584);
+    db.remove(&584).unwrap();
 
     assert_eq!(db.get(&584), None);
 }
 
-// #[test]
+#[test]
 pub fn binary_tree_test2() {
-    use binbuf::impls::{ArbNum, arb_num};
-    init();
-    let entry = "What's up!?".to_string();
-    let mut db = unsafe {
-        storage::BinaryTree::<ArbNum<4, u64>, u64, TestEntry1>::create(
-            storage::Fixed::create(open_file!("./local/binary_tree1_nodes"), 10).unwrap(),
-            storage::Fixed::create(open_file!("./local/binary_tree_free_ids"), 10).unwrap(),
-            open_file!("./local/binary_tree1_header")
-        ).unwrap()
-    };
+    use binbuf::impls::ArbNum;
+    let dir = init("binary_tree_test2");
+    let mut db = unsafe { open_binary_tree::<ArbNum<4, u64>, u64, TestEntry1, ()>(&dir, OpenMode::New) };
 
     let mut rng = rand::thread_rng();
     let mut keys = Vec::new();
 
-    for i in 0 .. 100 {
+    for _ in 0 .. 100 {
         let key = rng.r#gen::<u64>();
         if !db.add(&key, &TestEntry1 { idx: 5, opt: Some(false) }).unwrap() {
             keys.push(key);
@@ -251,4 +485,1062 @@ pub fn binary_tree_test2() {
             panic!("Key {key} doesn't exist! Failed to remove!");
         }
     }
-}
\ No newline at end of file
+}
+
+// Height of an AVL tree is at most about 1.44 * log2(len + 2).
+fn assert_balanced<I: storage::binary_tree::NodeId, K: binbuf::fixed::Decode + std::fmt::Debug, V: binbuf::Fixed, S: storage::binary_tree::NodeSize>(
+    db: &storage::BinaryTree<I, K, V, S>,
+) {
+    let stats = db.stats();
+    assert!(
+        stats.depth as f64 <= 1.45 * ((stats.len + 2) as f64).log2(),
+        "Tree of {} items is {} deep", stats.len, stats.depth
+    );
+}
+
+#[test]
+pub fn binary_tree_balance_test() {
+    let dir = init("binary_tree_balance_test");
+    let mut db = unsafe { open_binary_tree::<u64, u64, u64, ()>(&dir, OpenMode::New) };
+    let mut model = std::collections::BTreeMap::new();
+
+    // Ascending keys would make a linked list out of an unbalanced tree.
+    for key in 0 .. 1000u64 {
+        assert!(!db.add(&key, &(key * 2)).unwrap());
+        model.insert(key, key * 2);
+    }
+    assert_balanced(&db);
+
+    let mut rng = rand::thread_rng();
+    for step in 0 .. 5000 {
+        let key = rng.gen_range(0 .. 2000u64);
+        if rng.gen_bool(0.5) {
+            assert_eq!(db.add(&key, &step).unwrap(), model.contains_key(&key));
+            model.entry(key).or_insert(step);
+        } else {
+            assert_eq!(db.remove(&key).unwrap(), model.remove(&key).is_none());
+        }
+        if step % 500 == 0 {
+            assert_balanced(&db);
+        }
+    }
+    assert_balanced(&db);
+    assert_eq!(db.len(), model.len() as u64);
+    drop(db);
+
+    let db = unsafe { open_binary_tree::<u64, u64, u64, ()>(&dir, OpenMode::Existing) };
+    assert_eq!(db.len(), model.len() as u64);
+    for (key, value) in &model {
+        assert_eq!(db.get(key), Some(*value));
+    }
+    assert_eq!(db.get(&2000), None);
+    assert_balanced(&db);
+}
+
+#[test]
+pub fn binary_tree_entry_test() {
+    use storage::binary_tree::search;
+    let dir = init("binary_tree_entry_test");
+    let mut db = unsafe { open_binary_tree::<u64, u64, u64, ()>(&dir, OpenMode::New) };
+
+    // Vacant: `or_insert` inserts, `and_modify` does nothing and `insert` has no previous value.
+    for key in 0 .. 1000u64 {
+        let entry = db.entry(&key).and_modify(|_| panic!("Key {key} doesn't exist yet!"));
+        assert!(!entry.is_occupied());
+        if key % 2 == 0 {
+            let buf = entry.or_insert(&key).unwrap();
+            assert_eq!(binbuf::fixed::decode::<u64, _>(buf), key);
+        } else {
+            assert_eq!(entry.insert(&key).unwrap(), None);
+        }
+    }
+    assert_eq!(db.len(), 1000);
+    assert_balanced(&db);
+
+    // Occupied: `or_insert` keeps the value, `and_modify` and `insert` change it.
+    for key in 0 .. 1000u64 {
+        let buf = db.entry(&key).or_insert(&0).unwrap();
+        assert_eq!(binbuf::fixed::decode::<u64, _>(buf), key);
+        db.entry(&key).and_modify(|buf| binbuf::fixed::Readable::write_to(&(key + 1), buf));
+        assert_eq!(db.entry(&key).insert(&(key * 2)).unwrap(), Some(key + 1));
+    }
+    assert_eq!(db.len(), 1000);
+    for key in 0 .. 1000u64 {
+        assert_eq!(db.get(&key), Some(key * 2));
+    }
+
+    // Removing through entries keeps the tree balanced as well.
+    for key in (0 .. 1000u64).filter(|key| key % 3 != 0) {
+        match db.entry(&key) {
+            search::Value::Occupied(found) => assert_eq!(found.remove_entry().unwrap(), (key, key * 2)),
+            search::Value::Vacant(_) => panic!("Key {key} doesn't exist!"),
+        }
+        assert!(matches!(db.entry(&key), search::Value::Vacant(_)));
+    }
+    assert_eq!(db.len(), 334);
+    assert_balanced(&db);
+    for key in 0 .. 1000u64 {
+        assert_eq!(db.get(&key), (key % 3 == 0).then_some(key * 2));
+    }
+}
+
+unsafe fn open_b_tree<K: binbuf::Fixed, V: binbuf::Fixed>(dir: &Path, mode: OpenMode) -> Result<storage::BTree<K, V>, storage::b_tree::OpenError> {
+    storage::BTree::open(storage::b_tree::OpenConfig {
+        mode,
+        files: storage::b_tree::OpenFiles {
+            pages: open_file!(dir.join("pages")),
+            header: open_file!(dir.join("header")),
+        },
+        max_margins: storage::b_tree::OpenMaxMargins {
+            pages: 10,
+        },
+    })
+}
+
+// Key which takes a quarter of a page, so that both leaf and internal pages split after a few items.
+fn b_tree_key(idx: u64) -> [u8; 1000] {
+    let mut key = [0; 1000];
+    key[.. 8].copy_from_slice(&idx.to_be_bytes());
+    key
+}
+
+#[test]
+pub fn b_tree_test() {
+    let dir = init("b_tree_test");
+    let mut db = unsafe { open_b_tree::<[u8; 1000], u64>(&dir, OpenMode::New) }.unwrap();
+
+    let mut rng = rand::thread_rng();
+    let mut keys = (0 .. 2000u64).collect::<Vec<_>>();
+    for idx in (1 .. keys.len()).rev() {
+        keys.swap(idx, rng.gen_range(0 ..= idx));
+    }
+    for &key in &keys {
+        assert!(!db.add(&b_tree_key(key), &key).unwrap());
+    }
+    assert!(db.add(&b_tree_key(keys[0]), &0).unwrap());
+    assert_eq!(db.len(), 2000);
+
+    for key in (0 .. 2000).step_by(3) {
+        assert!(!db.remove(&b_tree_key(key)));
+    }
+    assert!(db.remove(&b_tree_key(0)));
+    drop(db);
+
+    let db = unsafe { open_b_tree::<[u8; 1000], u64>(&dir, OpenMode::Existing) }.unwrap();
+    for key in 0 .. 2000 {
+        assert_eq!(db.get(&b_tree_key(key)), (key % 3 != 0).then_some(key));
+    }
+    let items = db.iter().map(|(_, value_buf)| binbuf::fixed::decode::<u64, _>(value_buf)).collect::<Vec<_>>();
+    assert_eq!(items, (0 .. 2000).filter(|key| key % 3 != 0).collect::<Vec<_>>());
+    assert_eq!(db.len(), items.len() as u64);
+}
+
+#[test]
+pub fn b_tree_entry_too_big_test() {
+    let dir = init("b_tree_entry_too_big_test");
+    let result = unsafe { open_b_tree::<[u8; 2000], u64>(&dir, OpenMode::New) };
+    assert!(matches!(result, Err(storage::b_tree::OpenError::EntryTooBig)));
+}
+
+#[test]
+pub fn binary_tree_order_test() {
+    let dir = init("binary_tree_order_test");
+    let mut db = unsafe { open_binary_tree::<u64, u64, u64, ()>(&dir, OpenMode::New) };
+    for key in (0 .. 100u64).map(|idx| idx * 10) {
+        db.add(&key, &(key + 1)).unwrap();
+    }
+    let key = |item: Option<(binbuf::BufConst<u64>, binbuf::BufConst<u64>)>| item.map(|(key_buf, _)| binbuf::fixed::decode::<u64, _>(key_buf));
+
+    assert_eq!(db.iter().map(|item| key(Some(item)).unwrap()).collect::<Vec<_>>(), (0 .. 100).map(|idx| idx * 10).collect::<Vec<_>>());
+    assert_eq!(db.rev().map(|item| key(Some(item)).unwrap()).collect::<Vec<_>>(), (0 .. 100).rev().map(|idx| idx * 10).collect::<Vec<_>>());
+    assert_eq!(db.range::<&u64>(&15 .. &40).map(|item| key(Some(item)).unwrap()).collect::<Vec<_>>(), vec![20, 30]);
+    assert_eq!(db.range::<&u64>(&20 ..= &40).map(|item| key(Some(item)).unwrap()).collect::<Vec<_>>(), vec![20, 30, 40]);
+    assert_eq!(db.range::<&u64>(&41 .. &45).count(), 0);
+    assert_eq!(key(db.first()), Some(0));
+    assert_eq!(key(db.last()), Some(990));
+
+    assert_eq!(key(db.lower_bound(&20)), Some(20));
+    assert_eq!(key(db.lower_bound(&21)), Some(30));
+    assert_eq!(key(db.upper_bound(&20)), Some(30));
+    assert_eq!(key(db.upper_bound(&990)), None);
+    assert_eq!(key(db.floor(&20)), Some(20));
+    assert_eq!(key(db.floor(&29)), Some(20));
+    assert_eq!(key(db.predecessor(&20)), Some(10));
+    assert_eq!(key(db.predecessor(&0)), None);
+    assert_eq!(key(db.successor(&25)), Some(30));
+}
+
+#[test]
+pub fn binary_tree_multi_test() {
+    let dir = init("binary_tree_multi_test");
+    let mut db = unsafe { storage::BinaryTreeMulti::<u64, u64, u64>::open(binary_tree_config(&dir, OpenMode::New)) }.unwrap();
+    let values = |db: &storage::BinaryTreeMulti<u64, u64, u64>, key: u64| {
+        db.get_all(&key).map(|buf| binbuf::fixed::decode::<u64, _>(buf)).collect::<Vec<_>>()
+    };
+
+    // Values of the same key are ordered regardless of insertion order.
+    for value in [5, 1, 3, 4, 2] {
+        db.insert(&10, &value).unwrap();
+    }
+    db.insert(&5, &100).unwrap();
+    db.insert(&20, &0).unwrap();
+    db.insert(&10, &3).unwrap();
+    assert_eq!(db.len(), 8);
+    assert_eq!(values(&db, 10), [1, 2, 3, 3, 4, 5]);
+    assert_eq!(db.get_all(&10).rev().map(|buf| binbuf::fixed::decode::<u64, _>(buf)).collect::<Vec<_>>(), [5, 4, 3, 3, 2, 1]);
+    assert_eq!(values(&db, 5), [100]);
+    assert_eq!(values(&db, 15), []);
+
+    // Removing a pair keeps the other values of the key.
+    assert!(!db.remove(&10, &4).unwrap());
+    assert!(!db.contains(&10, &4));
+    assert!(db.remove(&10, &4).unwrap());
+    assert_eq!(values(&db, 10), [1, 2, 3, 3, 5]);
+
+    // Equal pairs are removed one at a time.
+    assert!(!db.remove(&10, &3).unwrap());
+    assert!(db.contains(&10, &3));
+    assert!(!db.remove(&10, &3).unwrap());
+    assert!(!db.contains(&10, &3));
+    assert_eq!(values(&db, 10), [1, 2, 5]);
+    assert!(db.contains_key(&10));
+
+    assert!(!db.remove(&5, &100).unwrap());
+    assert!(!db.contains_key(&5));
+    assert!(!db.contains(&5, &100));
+    assert_eq!(db.len(), 4);
+    drop(db);
+
+    let db = unsafe { storage::BinaryTreeMulti::<u64, u64, u64>::open(binary_tree_config(&dir, OpenMode::Existing)) }.unwrap();
+    assert_eq!(values(&db, 10), [1, 2, 5]);
+    assert_eq!(values(&db, 20), [0]);
+}
+
+unsafe fn open_dynamic_binary_tree<K: storage::dynamic_binary_tree::Storing, V: storage::dynamic_binary_tree::Storing>(
+    dir: &Path,
+    mode: OpenMode,
+    keys: K::Files,
+    key_margins: K::MaxMargins,
+    values: V::Files,
+    value_margins: V::MaxMargins,
+) -> storage::DynamicBinaryTree<u64, K, V>
+where K::Stored: binbuf::fixed::Decode + std::fmt::Debug {
+    storage::DynamicBinaryTree::open(storage::dynamic_binary_tree::OpenConfig {
+        mode,
+        files: storage::dynamic_binary_tree::OpenFiles {
+            nodes: open_file!(dir.join("nodes")),
+            free_ids: open_file!(dir.join("free_ids")),
+            header: open_file!(dir.join("header")),
+            keys,
+            values,
+        },
+        max_margins: storage::dynamic_binary_tree::OpenMaxMargins {
+            nodes: 10,
+            free_ids: 10,
+            keys: key_margins,
+            values: value_margins,
+        },
+    }).unwrap()
+}
+
+fn separate_files(dir: &Path, name: &str) -> storage::dynamic_binary_tree::SeparateFiles {
+    storage::dynamic_binary_tree::SeparateFiles {
+        entries: open_file!(dir.join(format!("{name}_entries"))),
+        free_locations: open_file!(dir.join(format!("{name}_free_locations"))),
+    }
+}
+
+fn separate_max_margins() -> storage::dynamic_binary_tree::SeparateMaxMargins {
+    storage::dynamic_binary_tree::SeparateMaxMargins { entries: 10, free_locations: 10 }
+}
+
+#[test]
+pub fn dynamic_binary_tree_test() {
+    use storage::dynamic_binary_tree::{Inline, Separate};
+
+    let dir = init("dynamic_binary_tree_test");
+    let open = |mode| unsafe {
+        open_dynamic_binary_tree::<Separate<String>, Inline<u64>>(&dir, mode, separate_files(&dir, "keys"), separate_max_margins(), (), ())
+    };
+    let mut db = open(OpenMode::New);
+    for idx in 0 .. 300u64 {
+        assert!(!db.add(&format!("user{idx}"), &idx).unwrap());
+    }
+    assert!(db.add("user7", &0).unwrap());
+    for idx in (0 .. 300u64).filter(|idx| idx % 2 == 0) {
+        assert!(!db.remove(&format!("user{idx}")).unwrap());
+    }
+    assert!(!db.set("user7", &700).unwrap());
+    drop(db);
+
+    let db = open(OpenMode::Existing);
+    assert_eq!(db.len(), 150);
+    assert_eq!(db.get("user7"), Some(700));
+    assert_eq!(db.get("user9"), Some(9));
+    assert_eq!(db.get("user8"), None);
+    let keys = db.iter().map(|(key_buf, _)| binbuf::dynamic::decode::<String>(key_buf).0).collect::<Vec<_>>();
+    let mut expected = (0 .. 300u64).filter(|idx| idx % 2 == 1).map(|idx| format!("user{idx}")).collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(keys, expected);
+
+    let dir = init("dynamic_binary_tree_test_values");
+    let open = |mode| unsafe {
+        open_dynamic_binary_tree::<Inline<u64>, Separate<String>>(&dir, mode, (), (), separate_files(&dir, "values"), separate_max_margins())
+    };
+    let mut db = open(OpenMode::New);
+    for idx in 0 .. 100u64 {
+        db.add(&idx, &"x".repeat(idx as usize)).unwrap();
+    }
+    assert!(!db.set(&5, "a much longer value than before").unwrap());
+    assert!(!db.remove(&6).unwrap());
+    drop(db);
+
+    let db = open(OpenMode::Existing);
+    assert_eq!(db.len(), 99);
+    assert_eq!(db.get(&5).as_deref(), Some("a much longer value than before"));
+    assert_eq!(db.get(&7), Some("x".repeat(7)));
+    assert_eq!(db.get(&6), None);
+}
+
+// Iterator which reports a wrong length.
+struct WrongLenIter<T>(T, usize);
+
+impl<T: Iterator> Iterator for WrongLenIter<T> {
+    type Item = T::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.1, Some(self.1))
+    }
+}
+
+impl<T: Iterator> ExactSizeIterator for WrongLenIter<T> {}
+
+#[test]
+pub fn binary_tree_from_sorted_iter_test() {
+    let dir = init("binary_tree_from_sorted_iter_test");
+    let items = (0 .. 100u64).map(|key| (key, key * 2)).collect::<Vec<_>>();
+    drop(unsafe { open_binary_tree::<u64, u64, u64, ()>(&dir, OpenMode::New) });
+
+    // Existing empty tree is loaded, instead of being replaced by a new one.
+    let db = unsafe { storage::BinaryTree::<u64, u64, u64>::from_sorted_iter(
+        binary_tree_config(&dir, OpenMode::Existing),
+        items.iter().map(|(key, value)| (key, value))
+    ) }.unwrap();
+    assert_eq!(db.len(), 100);
+    assert_eq!(db.get(&42), Some(84));
+    drop(db);
+
+    let result = unsafe { storage::BinaryTree::<u64, u64, u64>::from_sorted_iter(
+        binary_tree_config(&dir, OpenMode::Existing),
+        items.iter().map(|(key, value)| (key, value))
+    ) };
+    assert!(matches!(result, Err(storage::binary_tree::FromSortedIterError::BulkLoad(storage::binary_tree::BulkLoadError::NodesNotEmpty))));
+
+    for (len, real_len) in [(100, 50), (50, 100)] {
+        let mut db = unsafe { open_binary_tree::<u64, u64, u64, ()>(&dir, OpenMode::New) };
+        let result = db.bulk_load(WrongLenIter(items[.. real_len].iter().map(|(key, value)| (key, value)), len));
+        assert!(matches!(result, Err(storage::binary_tree::BulkLoadError::WrongLen)));
+        assert!(db.is_empty());
+        db.bulk_load(items.iter().map(|(key, value)| (key, value))).unwrap();
+        assert_eq!(db.len(), 100);
+        assert_balanced(&db);
+    }
+}
+
+#[test]
+pub fn binary_tree_bulk_load_rollback_test() {
+    let dir = init("binary_tree_bulk_load_rollback_test");
+    let items = (0 .. 100u64).map(|key| (key, key * 2)).collect::<Vec<_>>();
+    let mut db = unsafe { open_binary_tree::<u64, u64, u64, ()>(&dir, OpenMode::New) };
+    db.attach_bloom(unsafe { open_bloom::<u64>(&dir, OpenMode::New) }).unwrap();
+
+    let result = db.bulk_load(WrongLenIter(items[.. 50].iter().map(|(key, value)| (key, value)), 100));
+    assert!(matches!(result, Err(storage::binary_tree::BulkLoadError::WrongLen)));
+    assert!(db.is_empty());
+    assert!(items.iter().all(|(key, _)| db.get(key).is_none()));
+    let bloom = db.detach_bloom().unwrap();
+    assert!(items.iter().all(|(key, _)| !bloom.may_contain(key)));
+    drop((db, bloom));
+
+    let db = unsafe { open_binary_tree::<u64, u64, u64, ()>(&dir, OpenMode::Existing) };
+    assert_eq!(db.len(), 0);
+    assert_eq!(db.iter().count(), 0);
+}
+
+#[test]
+pub fn binary_tree_size_test() {
+    let dir = init("binary_tree_size_test");
+    let mut db = unsafe { open_binary_tree::<u64, u64, u64, u64>(&dir, OpenMode::New) };
+    let mut rng = rand::thread_rng();
+    let mut keys = Vec::new();
+    for _ in 0 .. 500 {
+        let key = rng.gen_range(0 .. 10000u64);
+        if !db.add(&key, &key).unwrap() {
+            keys.push(key);
+        }
+    }
+    for key in keys.iter().step_by(3) {
+        assert!(!db.remove(key).unwrap());
+    }
+    keys = keys.into_iter().enumerate().filter(|(idx, _)| idx % 3 != 0).map(|(_, key)| key).collect();
+    keys.sort();
+    drop(db);
+
+    let db = unsafe { open_binary_tree::<u64, u64, u64, u64>(&dir, OpenMode::Existing) };
+    for (idx, key) in keys.iter().enumerate() {
+        let (key_buf, _) = db.nth(idx as u64).unwrap();
+        assert_eq!(binbuf::fixed::decode::<u64, _>(key_buf), *key);
+        assert_eq!(db.rank(key), idx as u64);
+    }
+    assert!(db.nth(keys.len() as u64).is_none());
+    assert_eq!(db.rank(&10000), keys.len() as u64);
+}
+
+#[test]
+pub fn binary_tree_compared_test() {
+    use binbuf::impls::ordering::{AsciiCaseInsensitive, Compared, Reverse};
+
+    let dir = init("binary_tree_compared_test");
+    let mut db = unsafe { open_binary_tree::<u64, Compared<[u8; 4], AsciiCaseInsensitive>, u64, ()>(&dir, OpenMode::New) };
+    for (idx, key) in [b"beta", b"ALFA", b"Gama", b"delt"].into_iter().enumerate() {
+        assert!(!db.add(Compared::new(key), &(idx as u64)).unwrap());
+    }
+    assert!(db.add(Compared::new(b"BETA"), &10).unwrap());
+    drop(db);
+
+    let db = unsafe { open_binary_tree::<u64, Compared<[u8; 4], AsciiCaseInsensitive>, u64, ()>(&dir, OpenMode::Existing) };
+    assert_eq!(db.get(Compared::new(b"alfa")), Some(1));
+    assert_eq!(db.get(Compared::new(b"GAMA")), Some(2));
+    assert!(!db.contains_key(Compared::new(b"epsi")));
+    let keys = db.iter().map(|(key_buf, _)| binbuf::fixed::decode::<Compared<[u8; 4], AsciiCaseInsensitive>, _>(key_buf).into_inner()).collect::<Vec<_>>();
+    assert_eq!(keys, vec![*b"ALFA", *b"beta", *b"delt", *b"Gama"]);
+
+    let dir = init("binary_tree_compared_test_reverse");
+    let mut db = unsafe { open_binary_tree::<u64, Reverse<u64>, u64, ()>(&dir, OpenMode::New) };
+    for key in 0 .. 10u64 {
+        db.add(&Reverse::new(key), &key).unwrap();
+    }
+    let keys = db.iter().map(|(_, value_buf)| binbuf::fixed::decode::<u64, _>(value_buf)).collect::<Vec<_>>();
+    assert_eq!(keys, (0 .. 10).rev().collect::<Vec<_>>());
+    assert_eq!(db.get(Compared::new(&3u64)), Some(3));
+}
+
+unsafe fn open_hash_index<K: binbuf::Fixed, V: binbuf::Fixed>(dir: &Path, mode: OpenMode) -> storage::HashIndex<K, V> {
+    storage::HashIndex::open(storage::hash_index::OpenConfig {
+        mode,
+        files: storage::hash_index::OpenFiles {
+            buckets: open_file!(dir.join("buckets")),
+            overflow_slots: open_file!(dir.join("overflow_slots")),
+            free_ids: open_file!(dir.join("free_ids")),
+            header: open_file!(dir.join("header")),
+        },
+        max_margins: storage::hash_index::OpenMaxMargins {
+            buckets: 10,
+            overflow_slots: 10,
+            free_ids: 10,
+        },
+    }).unwrap()
+}
+
+#[test]
+pub fn hash_index_test() {
+    let dir = init("hash_index_test");
+    let mut db = unsafe { open_hash_index::<u64, u64>(&dir, OpenMode::New) };
+    for key in 0 .. 2000u64 {
+        assert!(!db.add(&key, &(key * 2)).unwrap());
+        // Every key must still be found after buckets are split.
+        if key % 97 == 0 {
+            assert!((0 ..= key).all(|key| db.get(&key) == Some(key * 2)));
+        }
+    }
+    assert!(db.add(&7, &0).unwrap());
+    for key in (0 .. 2000u64).filter(|key| key % 3 == 0) {
+        assert!(!db.remove(&key).unwrap());
+    }
+    assert!(db.remove(&3).unwrap());
+    drop(db);
+
+    let mut db = unsafe { open_hash_index::<u64, u64>(&dir, OpenMode::Existing) };
+    assert_eq!(db.len(), 1333);
+    for key in 0 .. 2000u64 {
+        assert_eq!(db.get(&key), (key % 3 != 0).then_some(key * 2));
+    }
+    let mut keys = db.iter().map(|(key_buf, _)| binbuf::fixed::decode::<u64, _>(key_buf)).collect::<Vec<_>>();
+    keys.sort();
+    assert_eq!(keys, (0 .. 2000).filter(|key| key % 3 != 0).collect::<Vec<_>>());
+
+    for key in (0 .. 2000u64).filter(|key| key % 3 == 0) {
+        assert!(!db.add(&key, &key).unwrap());
+    }
+    assert_eq!(db.len(), 2000);
+    assert_eq!(db.get(&3), Some(3));
+}
+
+unsafe fn open_radix_trie<V: binbuf::Fixed>(dir: &Path, mode: OpenMode) -> storage::RadixTrie<V> {
+    storage::RadixTrie::open(storage::radix_trie::OpenConfig {
+        mode,
+        files: storage::radix_trie::OpenFiles {
+            nodes: open_file!(dir.join("nodes")),
+            free_ids: open_file!(dir.join("free_ids")),
+            header: open_file!(dir.join("header")),
+            label_entries: open_file!(dir.join("label_entries")),
+            label_free_locations: open_file!(dir.join("label_free_locations")),
+        },
+        max_margins: storage::radix_trie::OpenMaxMargins {
+            nodes: 10,
+            free_ids: 10,
+            label_entries: 10,
+            label_free_locations: 10,
+        },
+    }).unwrap()
+}
+
+#[test]
+pub fn radix_trie_test() {
+    let dir = init("radix_trie_test");
+    let mut db = unsafe { open_radix_trie::<u64>(&dir, OpenMode::New) };
+    // Edges are split when a key ends in the middle of a label, or branches off from it.
+    let keys = ["romane", "romanus", "romulus", "rubens", "ruber", "rubicon", "rubicundus", "rom", "r", ""];
+    for (idx, key) in keys.iter().enumerate() {
+        assert!(!db.add(key, &(idx as u64)).unwrap());
+    }
+    assert!(db.add("ruber", &0).unwrap());
+    for (idx, key) in keys.iter().enumerate() {
+        assert_eq!(db.get(key), Some(idx as u64));
+    }
+    assert_eq!(db.get("roma"), None);
+    assert_eq!(db.get("rubi"), None);
+    assert_eq!(db.get("romanesque"), None);
+
+    let prefixed = db.prefix_iter("rub").map(|(key, _)| String::from_utf8(key).unwrap()).collect::<Vec<_>>();
+    assert_eq!(prefixed, vec!["rubens", "ruber", "rubicon", "rubicundus"]);
+    let prefixed = db.prefix_iter("roma").map(|(key, _)| String::from_utf8(key).unwrap()).collect::<Vec<_>>();
+    assert_eq!(prefixed, vec!["romane", "romanus"]);
+    assert_eq!(db.prefix_iter("rx").count(), 0);
+    assert_eq!(db.longest_prefix_match("romanesque").map(|(len, _)| len), Some(6));
+    assert_eq!(db.longest_prefix_match("romantic").map(|(len, _)| len), Some(3));
+    assert_eq!(db.longest_prefix_match("x").map(|(len, _)| len), Some(0));
+
+    // Removing keys merges nodes which are left with one child and no value.
+    for key in ["rom", "romane", "rubicon", "r"] {
+        assert!(!db.remove(key).unwrap());
+    }
+    assert!(db.remove("rom").unwrap());
+    assert!(db.remove("rubi").unwrap());
+    drop(db);
+
+    let db = unsafe { open_radix_trie::<u64>(&dir, OpenMode::Existing) };
+    assert_eq!(db.len(), 6);
+    let items = db.iter().map(|(key, value_buf)| (String::from_utf8(key).unwrap(), binbuf::fixed::decode::<u64, _>(value_buf))).collect::<Vec<_>>();
+    assert_eq!(items, vec![
+        ("".to_string(), 9),
+        ("romanus".to_string(), 1),
+        ("romulus".to_string(), 2),
+        ("rubens".to_string(), 3),
+        ("ruber".to_string(), 4),
+        ("rubicundus".to_string(), 6),
+    ]);
+    assert_eq!(db.longest_prefix_match("romanesque").map(|(len, _)| len), Some(0));
+}
+
+unsafe fn open_queue<E: binbuf::Fixed>(dir: &Path, mode: OpenMode, max_len: Option<u64>) -> storage::Queue<E> {
+    storage::Queue::open(storage::queue::OpenConfig {
+        mode,
+        files: storage::queue::OpenFiles {
+            slots: open_file!(dir.join("slots")),
+            header: open_file!(dir.join("header")),
+        },
+        max_margins: storage::queue::OpenMaxMargins {
+            slots: 10,
+        },
+        max_len,
+    }).unwrap()
+}
+
+#[test]
+pub fn queue_test() {
+    let dir = init("queue_test");
+    let mut db = unsafe { open_queue::<u64>(&dir, OpenMode::New, None) };
+    let mut expected = std::collections::VecDeque::new();
+    let mut next = 0u64;
+    // Pushes and pops are interleaved, so the ring wraps around and grows while the head isn't at the start.
+    for round in 0 .. 50 {
+        for _ in 0 .. round % 7 + 2 {
+            assert!(!db.push_back(&next).unwrap());
+            expected.push_back(next);
+            next += 1;
+        }
+        for _ in 0 .. round % 5 {
+            assert_eq!(db.pop_front(), expected.pop_front());
+        }
+        assert_eq!(db.iter().map(|buf| binbuf::fixed::decode::<u64, _>(buf)).collect::<Vec<_>>(), Vec::from(expected.clone()));
+        assert!(db.capacity() >= db.len());
+    }
+    let capacity = db.capacity();
+    drop(db);
+
+    let mut db = unsafe { open_queue::<u64>(&dir, OpenMode::Existing, None) };
+    assert_eq!(db.len(), expected.len() as u64);
+    assert_eq!(db.capacity(), capacity);
+    assert_eq!(db.peek(), expected.front().copied());
+    assert_eq!(db.get(1), expected.get(1).copied());
+    while let Some(entry) = db.pop_front() {
+        assert_eq!(Some(entry), expected.pop_front());
+    }
+    assert!(db.remove_front());
+    assert!(expected.is_empty());
+
+    let dir = init("queue_test_bounded");
+    let mut db = unsafe { open_queue::<u64>(&dir, OpenMode::New, Some(3)) };
+    for entry in 0 .. 3u64 {
+        assert!(!db.push_back(&entry).unwrap());
+    }
+    assert!(db.push_back(&3).unwrap());
+    assert_eq!(db.pop_front(), Some(0));
+    assert!(!db.push_back(&3).unwrap());
+    assert_eq!(db.capacity(), 3);
+    assert_eq!(db.iter().rev().map(|buf| binbuf::fixed::decode::<u64, _>(buf)).collect::<Vec<_>>(), vec![3, 2, 1]);
+}
+
+#[test]
+pub fn queue_growth_test() {
+    let dir = init("queue_growth_test");
+    let mut db = unsafe { open_queue::<u64>(&dir, OpenMode::New, None) };
+    let mut capacities = Vec::new();
+    for entry in 0 .. 1000u64 {
+        db.push_back(&entry).unwrap();
+        if capacities.last() != Some(&db.capacity()) {
+            capacities.push(db.capacity());
+        }
+    }
+    // Slots are doubled, instead of being added one by one.
+    assert_eq!(capacities, [1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 1024]);
+    assert!(db.iter().map(|buf| binbuf::fixed::decode::<u64, _>(buf)).eq(0 .. 1000));
+
+    // Growth is limited by the max length, even if fewer slots are added than there are before the head.
+    let dir = init("queue_growth_test_bounded");
+    let mut db = unsafe { open_queue::<u64>(&dir, OpenMode::New, Some(10)) };
+    for entry in 0 .. 8u64 {
+        db.push_back(&entry).unwrap();
+    }
+    assert_eq!(db.capacity(), 8);
+    for entry in 0 .. 6u64 {
+        assert_eq!(db.pop_front(), Some(entry));
+    }
+    for entry in 8 .. 16u64 {
+        assert!(!db.push_back(&entry).unwrap());
+    }
+    assert!(db.push_back(&16).unwrap());
+    assert_eq!(db.capacity(), 10);
+    assert!(db.iter().map(|buf| binbuf::fixed::decode::<u64, _>(buf)).eq(6 .. 16));
+}
+
+#[test]
+pub fn heap_test() {
+    let dir = init("heap_test");
+    let mut db = unsafe { storage::Heap::<u64>::open(OpenMode::New, open_file!(dir.join("entries")), 10) }.unwrap();
+    let mut rng = rand::thread_rng();
+    let mut entries = (0 .. 300).map(|_| rng.gen_range(0 .. 1000u64)).collect::<Vec<_>>();
+    for entry in &entries {
+        db.push(entry).unwrap();
+    }
+    entries.sort();
+    for entry in &entries[.. 100] {
+        assert_eq!(db.pop().unwrap(), Some(*entry));
+    }
+    drop(db);
+
+    let mut db = unsafe { storage::Heap::<u64>::open(OpenMode::Existing, open_file!(dir.join("entries")), 10) }.unwrap();
+    assert_eq!(db.len(), 200);
+    assert_eq!(db.peek(), Some(entries[100]));
+    for entry in &entries[100 ..] {
+        assert_eq!(db.pop().unwrap(), Some(*entry));
+    }
+    assert_eq!(db.pop().unwrap(), None);
+    assert!(db.remove_top().unwrap());
+
+    // Entries ordered by a field through its lens.
+    let dir = init("heap_test_key");
+    let mut db = unsafe {
+        storage::Heap::<TestEntry1, u64>::open_by_key(OpenMode::New, open_file!(dir.join("entries")), 10, TestEntry1::buf_idx)
+    }.unwrap();
+    for (idx, opt) in [(5, None), (1, Some(true)), (9, Some(false)), (3, None)] {
+        db.push(&TestEntry1 { idx, opt }).unwrap();
+    }
+    assert_eq!(db.pop().unwrap(), Some(TestEntry1 { idx: 1, opt: Some(true) }));
+    assert_eq!(db.pop().unwrap().map(|entry| entry.idx), Some(3));
+    assert_eq!(db.pop().unwrap().map(|entry| entry.idx), Some(5));
+    assert_eq!(db.pop().unwrap().map(|entry| entry.idx), Some(9));
+
+    // Max-heap with a reversed key.
+    let dir = init("heap_test_reverse");
+    let mut db = unsafe { storage::Heap::<binbuf::impls::Reverse<u64>>::open(OpenMode::New, open_file!(dir.join("entries")), 10) }.unwrap();
+    for entry in [4u64, 8, 1, 6] {
+        db.push(&binbuf::impls::Reverse::new(entry)).unwrap();
+    }
+    let popped = std::iter::from_fn(|| db.pop().unwrap()).map(|entry| entry.into_inner()).collect::<Vec<_>>();
+    assert_eq!(popped, vec![8, 6, 4, 1]);
+}
+
+unsafe fn open_multi_list<E: binbuf::Fixed>(dir: &Path, mode: OpenMode) -> storage::MultiList<E> {
+    storage::MultiList::open(storage::multi_list::OpenConfig {
+        mode,
+        files: storage::multi_list::OpenFiles {
+            lists: open_file!(dir.join("lists")),
+            list_free_ids: open_file!(dir.join("list_free_ids")),
+            blocks: open_file!(dir.join("blocks")),
+            block_free_ids: open_file!(dir.join("block_free_ids")),
+            items: open_file!(dir.join("items")),
+        },
+        max_margins: storage::multi_list::OpenMaxMargins {
+            lists: 10,
+            list_free_ids: 10,
+            blocks: 10,
+            block_free_ids: 10,
+            items: 10,
+        },
+    }).unwrap()
+}
+
+#[test]
+pub fn multi_list_test() {
+    let dir = init("multi_list_test");
+    let mut db = unsafe { open_multi_list::<u64>(&dir, OpenMode::New) };
+    let mut rng = rand::thread_rng();
+    let list_ids = (0 .. 4).map(|_| db.add_list().unwrap()).collect::<Vec<_>>();
+    let mut expected = vec![Vec::new(); list_ids.len()];
+    // Pushes to different lists are interleaved, so their blocks are mixed in the files.
+    for item in 0 .. 400u64 {
+        let idx = rng.gen_range(0 .. list_ids.len());
+        db.push(list_ids[idx], &item).unwrap();
+        expected[idx].push(item);
+    }
+    // Removals shift items across block boundaries and free emptied last blocks.
+    for _ in 0 .. 200 {
+        let idx = rng.gen_range(0 .. list_ids.len());
+        if expected[idx].is_empty() {
+            continue;
+        }
+        let item_idx = rng.gen_range(0 .. expected[idx].len());
+        assert!(!db.remove(list_ids[idx], item_idx as u64).unwrap());
+        expected[idx].remove(item_idx);
+    }
+    assert!(db.remove(list_ids[0], expected[0].len() as u64).unwrap());
+    drop(db);
+
+    let mut db = unsafe { open_multi_list::<u64>(&dir, OpenMode::Existing) };
+    for (list_id, items) in list_ids.iter().zip(&expected) {
+        assert_eq!(db.len(*list_id), items.len() as u64);
+        assert_eq!(db.iter(*list_id).map(|buf| binbuf::fixed::decode::<u64, _>(buf)).collect::<Vec<_>>(), *items);
+        assert_eq!(db.get(*list_id, 0), items.first().copied());
+    }
+
+    // Emptied list keeps working after its blocks are freed.
+    while !db.is_empty(list_ids[1]) {
+        db.remove(list_ids[1], 0).unwrap();
+    }
+    for item in 0 .. 20u64 {
+        db.push(list_ids[1], &item).unwrap();
+    }
+    assert_eq!(db.iter(list_ids[1]).map(|buf| binbuf::fixed::decode::<u64, _>(buf)).collect::<Vec<_>>(), (0 .. 20).collect::<Vec<_>>());
+
+    // Ids of removed lists are reused.
+    db.remove_list(list_ids[1]).unwrap();
+    assert_eq!(db.lists_len(), 3);
+    let list_id = db.add_list().unwrap();
+    assert_eq!(list_id, list_ids[1]);
+    assert!(db.is_empty(list_id));
+    db.remove_list(list_ids[3]).unwrap();
+    assert_eq!(db.add_list().unwrap(), list_ids[3]);
+    assert_eq!(db.lists_len(), 4);
+    assert_eq!(db.iter(list_ids[2]).map(|buf| binbuf::fixed::decode::<u64, _>(buf)).collect::<Vec<_>>(), expected[2]);
+}
+
+unsafe fn open_blob(dir: &Path, mode: OpenMode) -> storage::Blob {
+    storage::Blob::open(storage::blob::OpenConfig {
+        mode,
+        files: storage::blob::OpenFiles {
+            blobs: open_file!(dir.join("blobs")),
+            blob_free_ids: open_file!(dir.join("blob_free_ids")),
+            chunks: open_file!(dir.join("chunks")),
+            chunk_free_ids: open_file!(dir.join("chunk_free_ids")),
+        },
+        max_margins: storage::blob::OpenMaxMargins {
+            blobs: 10,
+            blob_free_ids: 10,
+            chunks: 10,
+            chunk_free_ids: 10,
+        },
+    }).unwrap()
+}
+
+#[test]
+pub fn blob_test() {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let dir = init("blob_test");
+    let mut db = unsafe { open_blob(&dir, OpenMode::New) };
+    let mut rng = rand::thread_rng();
+    let bytes = (0 .. storage::blob::CHUNK_LEN * 3 + 100).map(|_| rng.gen::<u8>()).collect::<Vec<_>>();
+    let small_bytes = b"small blob".to_vec();
+
+    let blob_id = db.add().unwrap();
+    let small_blob_id = db.add().unwrap();
+    // Writes are streamed in pieces which don't line up with chunks.
+    for (idx, piece) in bytes.chunks(1000).enumerate() {
+        db.writer(blob_id).write_all(piece).unwrap();
+        if idx == 2 {
+            db.writer(small_blob_id).write_all(&small_bytes).unwrap();
+        }
+    }
+    assert_eq!(db.len(blob_id), bytes.len() as u64);
+    drop(db);
+
+    let mut db = unsafe { open_blob(&dir, OpenMode::Existing) };
+    assert_eq!(db.get(blob_id), bytes);
+    assert_eq!(db.get(small_blob_id), small_bytes);
+
+    let mut reader = db.reader(blob_id);
+    let mut read_bytes = Vec::new();
+    reader.read_to_end(&mut read_bytes).unwrap();
+    assert_eq!(read_bytes, bytes);
+    let pos = storage::blob::CHUNK_LEN as u64 * 2 - 10;
+    reader.seek(SeekFrom::Start(pos)).unwrap();
+    let mut piece = [0u8; 20];
+    reader.read_exact(&mut piece).unwrap();
+    assert_eq!(piece, bytes[pos as usize .. pos as usize + 20]);
+    reader.seek(SeekFrom::End(-5)).unwrap();
+    read_bytes.clear();
+    reader.read_to_end(&mut read_bytes).unwrap();
+    assert_eq!(read_bytes, bytes[bytes.len() - 5 ..]);
+    assert!(reader.seek(SeekFrom::Current(-100000)).is_err());
+
+    // Truncating and appending reuses freed chunks.
+    let len = storage::blob::CHUNK_LEN as u64 + 50;
+    db.truncate(blob_id, len).unwrap();
+    assert_eq!(db.get(blob_id), bytes[.. len as usize]);
+    db.writer(blob_id).write_all(&small_bytes).unwrap();
+    assert_eq!(db.get(blob_id), [&bytes[.. len as usize], &small_bytes[..]].concat());
+    db.truncate(blob_id, 0).unwrap();
+    assert!(db.is_empty(blob_id));
+    db.writer(blob_id).write_all(&bytes[.. 10]).unwrap();
+    assert_eq!(db.get(blob_id), bytes[.. 10]);
+
+    db.remove(small_blob_id).unwrap();
+    let blob_id = db.add().unwrap();
+    assert_eq!(blob_id, small_blob_id);
+    assert!(db.is_empty(blob_id));
+}
+
+unsafe fn open_log<E: binbuf::Dynamic>(dir: &Path, mode: OpenMode) -> Result<storage::Log<E>, storage::log::OpenError> {
+    storage::Log::open(storage::log::OpenConfig {
+        mode,
+        dir: dir.to_path_buf(),
+        max_segment_len: 200,
+        max_margins: storage::log::OpenMaxMargins {
+            records: 100,
+            index: 10,
+        },
+    })
+}
+
+#[test]
+pub fn log_test() {
+    let dir = init("log_test");
+    let record = |offset: u64| format!("record {offset}");
+    let mut db = unsafe { open_log::<String>(&dir, OpenMode::New) }.unwrap();
+    assert!(db.is_empty());
+    let records_file_len = || std::fs::metadata(dir.join(format!("{:020}.records", 0))).unwrap().len();
+    assert_eq!(db.append(&record(0)).unwrap(), 0);
+    // Records file keeps the margin, so it isn't extended on every append.
+    let reserved_file_len = records_file_len();
+    for offset in 1 .. 5u64 {
+        assert_eq!(db.append(&record(offset)).unwrap(), offset);
+        assert_eq!(records_file_len(), reserved_file_len);
+    }
+    for offset in 5 .. 100u64 {
+        assert_eq!(db.append(&record(offset)).unwrap(), offset);
+    }
+    assert_eq!(db.append("").unwrap(), 100);
+    let segments_len = db.segments_len();
+    assert!(segments_len > 5);
+    drop(db);
+
+    let mut db = unsafe { open_log::<String>(&dir, OpenMode::Existing) }.unwrap();
+    assert_eq!(db.segments_len(), segments_len);
+    assert_eq!(db.len(), 101);
+    assert_eq!(db.get(42), Some(record(42)));
+    assert_eq!(db.get(100), Some(String::new()));
+    assert_eq!(db.get(101), None);
+    let offsets = db.iter_from(95).map(|(offset, _)| offset).collect::<Vec<_>>();
+    assert_eq!(offsets, (95 .. 101).collect::<Vec<_>>());
+    assert!(db.iter().take(100).all(|(offset, buf)| binbuf::dynamic::decode::<String>(buf).0 == record(offset)));
+
+    // Only segments whose records all come before the offset are removed.
+    let removed_len = db.remove_segments_before(50).unwrap();
+    assert!(removed_len > 0);
+    assert!(db.first_offset() <= 50);
+    assert_eq!(db.get(db.first_offset() - 1), None);
+    assert_eq!(db.get(50), Some(record(50)));
+    assert_eq!(db.iter_from(0).next().map(|(offset, _)| offset), Some(db.first_offset()));
+    let first_offset = db.first_offset();
+    assert_eq!(db.remove_segments_before(1000).unwrap(), segments_len - removed_len - 1);
+    assert_eq!(db.segments_len(), 1);
+    assert_eq!(db.append(&record(101)).unwrap(), 101);
+    drop(db);
+
+    // Files left by a removal which didn't finish are removed on open.
+    std::fs::write(dir.join(format!("{:020}.records", first_offset - 1)), b"").unwrap();
+    let db = unsafe { open_log::<String>(&dir, OpenMode::Existing) }.unwrap();
+    assert!(!dir.join(format!("{:020}.records", first_offset - 1)).exists());
+    assert_eq!(db.segments_len(), 1);
+    assert_eq!(db.get(101), Some(record(101)));
+    let last_base_offset = db.first_offset();
+    drop(db);
+
+    // Missing files of a kept segment are reported.
+    std::fs::remove_file(dir.join(format!("{last_base_offset:020}.index"))).unwrap();
+    assert!(matches!(
+        unsafe { open_log::<String>(&dir, OpenMode::Existing) },
+        Err(storage::log::OpenError::SegmentOpen(storage::log::SegmentOpenError::Io(_)))
+    ));
+}
+
+unsafe fn open_bloom<K: binbuf::Fixed>(dir: &Path, mode: OpenMode) -> storage::Bloom<K> {
+    storage::Bloom::open(storage::bloom::OpenConfig {
+        mode,
+        files: storage::bloom::OpenFiles {
+            bits: open_file!(dir.join("bloom_bits")),
+            header: open_file!(dir.join("bloom_header")),
+        },
+        max_margins: storage::bloom::OpenMaxMargins {
+            bits: 10,
+        },
+        expected_len: 1000,
+        false_positive_rate: 0.01,
+    }).unwrap()
+}
+
+#[test]
+pub fn bloom_test() {
+    let dir = init("bloom_test");
+    let mut db = unsafe { open_binary_tree::<u64, u64, u64, ()>(&dir, OpenMode::New) };
+    for key in (0 .. 500u64).map(|idx| idx * 2) {
+        db.add(&key, &key).unwrap();
+    }
+    // Keys which are already in the tree are added to the filter.
+    db.attach_bloom(unsafe { open_bloom::<u64>(&dir, OpenMode::New) }).unwrap();
+    for key in (500 .. 1000u64).map(|idx| idx * 2) {
+        db.add(&key, &key).unwrap();
+    }
+    for key in 0 .. 2000u64 {
+        assert_eq!(db.contains_key(&key), key % 2 == 0);
+        assert_eq!(db.contains_key(&key), db.search(&key).is_found());
+        assert_eq!(db.get(&key), (key % 2 == 0).then_some(key));
+    }
+    let bloom = db.detach_bloom().unwrap();
+    assert!((0 .. 1000u64).all(|idx| bloom.may_contain(&(idx * 2))));
+    // Rate of false positives stays close to the expected one.
+    let false_positives_len = (0 .. 1000u64).filter(|idx| bloom.may_contain(&(idx * 2 + 1))).count();
+    assert!(false_positives_len < 50);
+    let (bits_len, hashes_len) = (bloom.bits_len(), bloom.hashes_len());
+    drop(bloom);
+
+    // Existing filter keeps its bits and size.
+    let bloom = unsafe { open_bloom::<u64>(&dir, OpenMode::Existing) };
+    assert_eq!((bloom.bits_len(), bloom.hashes_len()), (bits_len, hashes_len));
+    assert!((0 .. 1000u64).all(|idx| bloom.may_contain(&(idx * 2))));
+
+    for false_positive_rate in [0.0, -0.5, 1.0, 2.0, f64::NAN] {
+        let result = unsafe { storage::Bloom::<u64>::open(storage::bloom::OpenConfig {
+            mode: OpenMode::New,
+            files: storage::bloom::OpenFiles {
+                bits: open_file!(dir.join("invalid_bloom_bits")),
+                header: open_file!(dir.join("invalid_bloom_header")),
+            },
+            max_margins: storage::bloom::OpenMaxMargins {
+                bits: 10,
+            },
+            expected_len: 1000,
+            false_positive_rate,
+        }) };
+        assert!(matches!(result, Err(storage::bloom::OpenError::InvalidFalsePositiveRate)));
+    }
+
+    let dir = init("bloom_test_hash_index");
+    let mut db = unsafe { open_hash_index::<u64, u64>(&dir, OpenMode::New) };
+    db.attach_bloom(unsafe { open_bloom::<u64>(&dir, OpenMode::New) }).unwrap();
+    for key in (0 .. 1000u64).map(|idx| idx * 2) {
+        db.add(&key, &key).unwrap();
+    }
+    assert!((0 .. 2000u64).all(|key| db.contains_key(&key) == (key % 2 == 0)));
+}
+
+unsafe fn open_interner<E: binbuf::Dynamic>(dir: &Path, mode: OpenMode) -> storage::Interner<E> {
+    storage::Interner::open(storage::interner::OpenConfig {
+        mode,
+        files: storage::interner::OpenFiles {
+            value_entries: open_file!(dir.join("value_entries")),
+            value_free_locations: open_file!(dir.join("value_free_locations")),
+            value_indices: open_file!(dir.join("value_indices")),
+            value_free_ids: open_file!(dir.join("value_free_ids")),
+            index_nodes: open_file!(dir.join("index_nodes")),
+            index_free_ids: open_file!(dir.join("index_free_ids")),
+            index_header: open_file!(dir.join("index_header")),
+            ref_counts: open_file!(dir.join("ref_counts")),
+        },
+        max_margins: storage::interner::OpenMaxMargins {
+            value_entries: 10,
+            value_free_locations: 10,
+            value_indices: 10,
+            value_free_ids: 10,
+            index_nodes: 10,
+            index_free_ids: 10,
+            ref_counts: 10,
+        },
+    }).unwrap()
+}
+
+#[test]
+pub fn interner_test() {
+    let dir = init("interner_test");
+    let mut db = unsafe { open_interner::<String>(&dir, OpenMode::New) };
+    let names = (0 .. 50).map(|idx| format!("name{idx}")).collect::<Vec<_>>();
+    let ids = names.iter().map(|name| db.intern(name).unwrap()).collect::<Vec<_>>();
+    // Equal values get the same id, and their reference count grows.
+    for (name, id) in names.iter().zip(&ids).step_by(2) {
+        assert_eq!(db.intern(name).unwrap().index, id.index);
+    }
+    assert_eq!(db.len(), 50);
+    assert_eq!(db.ref_count(ids[0]), Some(2));
+    assert_eq!(db.ref_count(ids[1]), Some(1));
+    assert_eq!(db.find("name7").map(|id| id.index), Some(ids[7].index));
+    assert!(db.find("missing").is_none());
+    drop(db);
+
+    let mut db = unsafe { open_interner::<String>(&dir, OpenMode::Existing) };
+    assert_eq!(db.len(), 50);
+    assert_eq!(db.resolve(ids[3]).as_deref(), Some("name3"));
+    // Values are removed once every interned copy is released.
+    for id in &ids {
+        assert!(!db.release(*id).unwrap());
+    }
+    assert_eq!(db.len(), 25);
+    assert!(db.resolve(ids[1]).is_none());
+    assert!(db.release(ids[1]).unwrap());
+    assert_eq!(db.ref_count(ids[0]), Some(1));
+    assert_eq!(db.resolve(ids[0]).as_deref(), Some("name0"));
+
+    let id = db.intern("name1").unwrap();
+    assert_eq!(db.ref_count(id), Some(1));
+    assert_eq!(db.find("name1").map(|id| id.index), Some(id.index));
+    let mut values = db.iter().map(|(_, buf)| binbuf::dynamic::decode::<String>(buf).0).collect::<Vec<_>>();
+    values.sort();
+    let mut expected = names.iter().step_by(2).cloned().chain(["name1".to_string()]).collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(values, expected);
+}
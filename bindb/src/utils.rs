@@ -14,4 +14,9 @@ pub fn index_array<T, const SIZE: usize, const INDEX_SIZE: usize>(value: &[T; SI
 
 pub fn index_array_mut<T, const SIZE: usize, const INDEX_SIZE: usize>(value: &mut [T; SIZE], at: usize) -> &mut [T; INDEX_SIZE] {
     unsafe { slice_to_array_mut(&mut value[at .. at + INDEX_SIZE]) }
-}
\ No newline at end of file
+}
+
+// FNV-1a hash.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}